@@ -0,0 +1,236 @@
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Select};
+use lazy_static::lazy_static;
+use log::{debug, info, trace, warn};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::course_downloader::course::{get_cookie, get_courses, queue_upload, Course};
+use crate::course_downloader::download::get_with_cookie;
+use crate::course_downloader::main::{download_path, Error};
+use crate::login::profile;
+use crate::public::html::to_text;
+use crate::public::logger::Logger;
+use crate::public::storage::{read_json, write_json};
+use crate::public::VOID_VEC;
+
+lazy_static! {
+    /// 最近一次获取到的公告 id 和所属的账号，未读数量每次按已读记录重新计算
+    static ref KNOWN: Mutex<Option<(String, Vec<String>)>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone)]
+pub struct Announcement {
+    pub id: String,
+    pub course: Course,
+    pub title: String,
+    pub content: String,
+    pub created_at: String,
+    pub uploads: Vec<Value>,
+}
+
+impl Announcement {
+    fn from_json(course: &Course, element: &Value) -> Self {
+        let get_str = |key: &str| {
+            element
+                .get(key)
+                .unwrap_or(&Value::Null)
+                .as_str()
+                .unwrap_or("")
+                .to_string()
+        };
+        Self {
+            id: element.get("id").unwrap_or(&Value::Null).to_string(),
+            course: course.clone(),
+            title: get_str("title"),
+            content: get_str("content"),
+            created_at: get_str("created_at"),
+            uploads: element
+                .get("uploads")
+                .unwrap_or(&Value::Null)
+                .as_array()
+                .unwrap_or(&*VOID_VEC)
+                .to_owned(),
+        }
+    }
+}
+
+fn seen_path() -> String {
+    format!("{}announcements.json", profile::data_path())
+}
+
+fn load_seen() -> HashSet<String> {
+    read_json(&seen_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+fn save_seen(seen: &HashSet<String>) {
+    let mut ids = seen.iter().collect::<Vec<_>>();
+    ids.sort();
+    if let Err(e) = write_json(&seen_path(), &serde_json::json!(ids)) {
+        warn!("保存已读公告失败 {}", e);
+    }
+}
+
+/// 主菜单中显示的名称，带上最近一次获取到的公告中未读的数量
+pub fn menu_label() -> String {
+    let unread = match &*KNOWN.lock().unwrap() {
+        Some((name, ids)) if *name == profile::current_name() => {
+            let seen = load_seen();
+            ids.iter().filter(|x| !seen.contains(*x)).count()
+        }
+        _ => 0,
+    };
+    match unread {
+        0 => "课程公告".to_string(),
+        n => format!("课程公告（{} 条未读）", n),
+    }
+}
+
+fn set_known(announcements: &[Announcement]) {
+    let ids = announcements.iter().map(|x| x.id.clone()).collect();
+    *KNOWN.lock().unwrap() = Some((profile::current_name(), ids));
+}
+
+pub fn get_course_announcements(course: &Course, cookie: &str) -> Result<Vec<Announcement>, Error> {
+    let resp = get_with_cookie(
+        format!("https://lnt.xmu.edu.cn/api/courses/{}/bulletins", course.id),
+        cookie,
+    )?;
+    let json: Value = match resp.json() {
+        Ok(v) => v,
+        Err(_) => return Err(Error::LoginDataInvalid),
+    };
+    trace!("公告 json = {}", json);
+    Ok(json
+        .get("bulletins")
+        .unwrap_or(&Value::Null)
+        .as_array()
+        .unwrap_or(&*VOID_VEC)
+        .iter()
+        .map(|x| Announcement::from_json(course, x))
+        .collect())
+}
+
+pub fn get_announcements(cookie: &str) -> Result<Vec<Announcement>, Error> {
+    let mut announcements = Vec::new();
+    for course in get_courses(cookie)? {
+        // 一门课程失败时跳过，其他课程的公告仍然显示
+        match get_course_announcements(&course, cookie) {
+            Ok(v) => announcements.extend(v),
+            Err(e) => {
+                warn!("获取课程 {} 的公告失败，已跳过", course.name);
+                e.logger();
+            }
+        }
+    }
+    announcements.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    debug!("获取到 {} 条公告", announcements.len());
+    Ok(announcements)
+}
+
+/// 启动、登录和切换账号后提示未读公告，没有登录时什么也不做
+pub fn startup() {
+    let cookie = match get_cookie() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    match get_announcements(&cookie) {
+        Ok(announcements) => {
+            set_known(&announcements);
+            let seen = load_seen();
+            if announcements.iter().all(|x| seen.contains(&x.id)) {
+                return;
+            }
+            info!("有新的课程公告：");
+            for x in announcements.iter().filter(|x| !seen.contains(&x.id)) {
+                info!("[{}] {}", x.course.name, x.title);
+            }
+        }
+        Err(e) => e.logger(),
+    }
+}
+
+pub fn main() {
+    match browse() {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+fn show(announcement: &Announcement, cookie: &str) -> Result<(), Error> {
+    println!(
+        "\n[{}] {}\n{}\n",
+        announcement.course.name, announcement.title, announcement.created_at
+    );
+    println!("{}\n", to_text(&announcement.content));
+    if announcement.uploads.is_empty() {
+        return Ok(());
+    }
+    for upload in &announcement.uploads {
+        println!(
+            "附件：{}",
+            upload
+                .get("name")
+                .unwrap_or(&Value::Null)
+                .as_str()
+                .unwrap_or("")
+        );
+    }
+    let download = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("下载附件？")
+        .default(true)
+        .interact()
+        .unwrap_or(false);
+    if download {
+        for upload in &announcement.uploads {
+            queue_upload(upload, cookie, &download_path(), false)?;
+        }
+    }
+    Ok(())
+}
+
+fn browse() -> Result<(), Error> {
+    let cookie = get_cookie()?;
+    let announcements = get_announcements(&cookie)?;
+    set_known(&announcements);
+    let mut seen = load_seen();
+    loop {
+        let mut choices = vec!["返回".to_string(), "全部标为已读".to_string()];
+        choices.extend(announcements.iter().map(|x| {
+            format!(
+                "{} [{}] {} {}",
+                match seen.contains(&x.id) {
+                    true => " ",
+                    false => "●",
+                },
+                x.course.name,
+                x.title,
+                x.created_at.get(..10).unwrap_or("")
+            )
+        }));
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("课程公告")
+            .default(0)
+            .items(&choices)
+            .interact()
+            .unwrap_or(0);
+        match selection {
+            0 => break,
+            1 => {
+                seen.extend(announcements.iter().map(|x| x.id.clone()));
+                save_seen(&seen);
+            }
+            n => {
+                let announcement = &announcements[n - 2];
+                show(announcement, &cookie)?;
+                seen.insert(announcement.id.clone());
+                save_seen(&seen);
+            }
+        }
+    }
+    Ok(())
+}
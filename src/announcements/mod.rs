@@ -0,0 +1,2 @@
+pub mod main;
+pub use main::main;
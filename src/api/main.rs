@@ -0,0 +1,312 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use log::{debug, error, info, warn};
+use rand::distr::{Alphanumeric, SampleString};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::course_downloader::course::{get_cookie, load_courses};
+use crate::course_downloader::main::{sync_course, Error};
+use crate::deadlines::main::get_upcoming;
+use crate::login::profile;
+use crate::login::status::{self, Status};
+use crate::public::download_file;
+use crate::public::logger::Logger;
+use crate::public::thread_manage;
+use crate::setting::config;
+
+const TOKEN_LEN: usize = 32;
+/// 处理请求的线程数，同步课程和查询作业比较慢，但也不需要太多
+const WORKERS: usize = 4;
+
+/// 读取令牌，没有时生成一个并保存
+fn token() -> String {
+    let token = config::api_token();
+    if !token.is_empty() {
+        return token;
+    }
+    let token = Alphanumeric.sample_string(&mut rand::rng(), TOKEN_LEN);
+    let saved = token.clone();
+    config::update(|x| x.api_token = saved);
+    token
+}
+
+fn reset_token() {
+    config::update(|x| x.api_token.clear());
+    info!("新的令牌：{}", token());
+}
+
+/// 令牌可以放在 `Authorization: Bearer` 头或 `token` 参数中
+fn authorized(request: &Request, token: &str) -> bool {
+    let header = request
+        .headers()
+        .iter()
+        .find(|x| x.field.equiv("Authorization"))
+        .map(|x| x.value.as_str().trim_start_matches("Bearer ").trim());
+    if header.is_some_and(|x| same_token(x, token)) {
+        return true;
+    }
+    query(request.url())
+        .iter()
+        .any(|(k, v)| k == "token" && same_token(v, token))
+}
+
+/// 比较时间不随相同前缀的长度变化，避免逐字符猜出令牌
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn query(url: &str) -> Vec<(String, String)> {
+    let query = match url.split_once('?') {
+        Some((_, q)) => q,
+        None => return Vec::new(),
+    };
+    query
+        .split('&')
+        .filter_map(|x| x.split_once('='))
+        .map(|(k, v)| {
+            let v = urlencoding::decode(v).map(|x| x.to_string());
+            (k.to_string(), v.unwrap_or_default())
+        })
+        .collect()
+}
+
+/// 课程中心的会话失效时返回 401，网络问题返回 503
+fn error(e: Error) -> (u16, Value) {
+    let code = match e {
+        Error::LoginDataInvalid => 401,
+        Error::NetworkFailure => 503,
+    };
+    (code, json!({ "error": e.get_logger().message() }))
+}
+
+fn get_status() -> (u16, Value) {
+    let profile = profile::current();
+    let mut ret = json!({
+        "profile": profile::current_name(),
+        "session_saved_at": profile.session_saved_at,
+        "session_expires": profile.session_expires,
+        "jw_logged_in": profile.jw_session.is_some(),
+    });
+    ret["status"] = match status::check() {
+        Status::NotLoggedIn => json!("not_logged_in"),
+        Status::Invalid => json!("invalid"),
+        Status::Unknown => json!("unknown"),
+        Status::Valid(user) => {
+            ret["name"] = json!(user.name);
+            ret["user_no"] = json!(user.user_no);
+            json!("valid")
+        }
+    };
+    (200, ret)
+}
+
+fn get_courses(refresh: bool) -> (u16, Value) {
+    match get_cookie().and_then(|x| load_courses(&x, refresh)) {
+        Ok(courses) => (200, json!(courses)),
+        Err(e) => error(e),
+    }
+}
+
+fn post_sync(course_id: &str) -> (u16, Value) {
+    match get_cookie().and_then(|x| sync_course(course_id, &x)) {
+        Ok(files) => (200, json!({ "queued": files })),
+        Err(e) => error(e),
+    }
+}
+
+fn get_queue() -> (u16, Value) {
+    let tasks = download_file::active_tasks()
+        .into_iter()
+        .map(|x| {
+            json!({
+                "id": x.task.id,
+                "url": x.task.url,
+                "file": x.task.file,
+                "started": x.started,
+                "current": x.current,
+                "total": x.total,
+            })
+        })
+        .collect::<Vec<_>>();
+    (200, json!(tasks))
+}
+
+/// 带 `id` 参数时只取消这一个任务，带 `file` 参数时取消保存到这个位置的任务
+fn post_cancel(query: &[(String, String)]) -> (u16, Value) {
+    let get = |key: &str| query.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    let ids = match (get("id"), get("file")) {
+        (Some(id), _) => match id.parse::<u64>() {
+            Ok(id) => vec![id],
+            Err(_) => return (404, json!({ "error": "没有这个任务" })),
+        },
+        (None, Some(file)) => download_file::active_tasks()
+            .into_iter()
+            .filter(|x| x.task.file == *file)
+            .map(|x| x.task.id)
+            .collect(),
+        (None, None) => {
+            download_file::cancel_all();
+            return (200, json!({ "ok": true }));
+        }
+    };
+    ids.into_iter().for_each(download_file::cancel_task);
+    (200, json!({ "ok": true }))
+}
+
+fn get_failed() -> (u16, Value) {
+    let tasks = download_file::failed_tasks()
+        .into_iter()
+        .map(|x| json!({ "url": x.url, "file": x.file }))
+        .collect::<Vec<_>>();
+    (200, json!(tasks))
+}
+
+fn post_retry(index: Option<&str>) -> (u16, Value) {
+    match index.map(|x| x.parse::<usize>()) {
+        None => download_file::retry_error_tasks(),
+        Some(Ok(i)) if i < download_file::failed_tasks().len() => {
+            download_file::retry_error_task(i)
+        }
+        Some(_) => return (404, json!({ "error": "没有这个失败任务" })),
+    }
+    (200, json!({ "ok": true }))
+}
+
+fn get_deadlines() -> (u16, Value) {
+    let deadlines = match get_cookie().and_then(|x| get_upcoming(&x)) {
+        Ok(v) => v,
+        Err(e) => return error(e),
+    };
+    let deadlines = deadlines
+        .into_iter()
+        .map(|x| {
+            json!({
+                "id": x.id,
+                "course_id": x.course.id,
+                "course": x.course.name,
+                "title": x.title,
+                "kind": x.kind.name(),
+                "start": x.start,
+                "end": x.end,
+                "status": x.status(),
+            })
+        })
+        .collect::<Vec<_>>();
+    (200, json!(deadlines))
+}
+
+fn route(method: &Method, url: &str) -> (u16, Value) {
+    let path = url.split('?').next().unwrap_or("");
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    let refresh = query(url).iter().any(|(k, v)| k == "refresh" && v == "1");
+    match (method, segments.as_slice()) {
+        (Method::Get, ["api", "status"]) => get_status(),
+        (Method::Get, ["api", "courses"]) => get_courses(refresh),
+        (Method::Post, ["api", "courses", id, "sync"]) => post_sync(id),
+        (Method::Get, ["api", "queue"]) => get_queue(),
+        (Method::Post, ["api", "queue", "cancel"]) => post_cancel(&query(url)),
+        (Method::Get, ["api", "failed"]) => get_failed(),
+        (Method::Post, ["api", "failed", "retry"]) => post_retry(None),
+        (Method::Post, ["api", "failed", index, "retry"]) => post_retry(Some(index)),
+        (Method::Get, ["api", "deadlines"]) => get_deadlines(),
+        _ => (404, json!({ "error": "没有这个接口" })),
+    }
+}
+
+fn handle(request: Request) {
+    debug!("接口请求 {} {}", request.method(), request.url());
+    let (code, body) = match authorized(&request, &token()) {
+        true => route(request.method(), request.url()),
+        false => (401, json!({ "error": "令牌无效" })),
+    };
+    let header = Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(code)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        warn!("接口响应失败 {}", e);
+    }
+}
+
+/// 在 127.0.0.1 上提供接口，一直运行
+pub fn serve() {
+    token();
+    let addr = format!("127.0.0.1:{}", config::get().api_port);
+    let server = match Server::http(&addr) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("无法监听 {} {}", addr, e);
+            return;
+        }
+    };
+    info!("本地接口已启动 http://{}/api/status", addr);
+    let server = Arc::new(server);
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap_or_default();
+    }
+}
+
+pub fn main() {
+    let config = config::get();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("本地接口（端口 {}）", config.api_port))
+        .default(0)
+        .item("在后台启动")
+        .item("显示令牌")
+        .item("重新生成令牌")
+        .item("返回")
+        .interact()
+        .unwrap_or(3);
+    match selection {
+        0 => {
+            thread_manage::execute("接口线程", serve);
+            info!("令牌：{}", token());
+        }
+        1 => info!("令牌：{}", token()),
+        2 => reset_token(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_comparison() {
+        assert!(same_token("abc123", "abc123"));
+        assert!(!same_token("abc124", "abc123"));
+        assert!(!same_token("abc12", "abc123"));
+        assert!(!same_token("", "abc123"));
+    }
+
+    #[test]
+    fn query_is_decoded() {
+        assert_eq!(
+            query("/api/queue/cancel?file=a%2Fb.pdf&x"),
+            vec![("file".to_string(), "a/b.pdf".to_string())]
+        );
+        assert!(query("/api/status").is_empty());
+    }
+}
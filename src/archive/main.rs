@@ -0,0 +1,358 @@
+use log::{info, warn, LevelFilter};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::{create_dir_all, File};
+use std::io::{copy, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::course_downloader::course::{get_activities, get_cookie, upload_path, Course};
+use crate::course_downloader::main::{download_path, Error as FetchError};
+use crate::course_downloader::search::select_courses;
+use crate::course_downloader::video::{self, is_video};
+use crate::public::html::to_text;
+use crate::public::ical::{now, parse_rfc3339};
+use crate::public::logger::{Logger, LoggerData};
+use crate::public::manifest::{locate, with_suffix};
+use crate::public::progress::format_size;
+use crate::public::storage::{sanitize, EXPORT_PATH};
+use crate::public::VOID_VEC;
+
+/// 本身已经压缩过的格式直接存储，不再压缩
+const STORED: &[&str] = &[
+    "mp4", "flv", "ts", "zip", "rar", "7z", "gz", "jpg", "jpeg", "png", "gif", "mp3", "docx",
+    "pptx", "xlsx",
+];
+
+pub enum Error {
+    Fetch(FetchError),
+    Write,
+}
+
+impl Logger for Error {
+    fn get_logger(&self) -> LoggerData {
+        match self {
+            Error::Fetch(e) => e.get_logger(),
+            Error::Write => LoggerData::new(LevelFilter::Error, "写入压缩包失败"),
+        }
+    }
+}
+
+impl From<FetchError> for Error {
+    fn from(e: FetchError) -> Self {
+        Error::Fetch(e)
+    }
+}
+
+struct ArchiveFile {
+    name: String,
+    size: u64,
+    /// 本地的位置和在压缩包中的位置，还没有下载时为 None
+    local: Option<(String, String)>,
+}
+
+struct Item {
+    title: String,
+    kind: String,
+    start: String,
+    end: String,
+    description: String,
+    files: Vec<ArchiveFile>,
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(|x| x.as_str()).unwrap_or("")
+}
+
+fn kind_name(kind: &str) -> &str {
+    match kind {
+        "material" => "资料",
+        "online_video" => "视频",
+        "lesson" | "lesson_replay" => "课堂回放",
+        "homework" => "作业",
+        "exam" => "测验",
+        "page" => "页面",
+        "link" => "链接",
+        other => other,
+    }
+}
+
+fn time(value: &Value, key: &str) -> String {
+    parse_rfc3339(get_str(value, key))
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// 为压缩包中的文件取一个不重复的名字
+fn entry_name(name: &str, used: &mut HashSet<String>) -> String {
+    let base = format!("files/{}", sanitize(name));
+    let ret = (1..)
+        .map(|i| match i {
+            1 => base.clone(),
+            i => with_suffix(&base, &format!(" ({})", i - 1)),
+        })
+        .find(|x| !used.contains(x))
+        .unwrap();
+    used.insert(ret.clone());
+    ret
+}
+
+fn collect(elements: &[Value], path: &str) -> Vec<Item> {
+    let mut used = HashSet::new();
+    let mut items = Vec::new();
+    for element in elements {
+        let uploads = element
+            .get("uploads")
+            .and_then(|x| x.as_array())
+            .unwrap_or(&*VOID_VEC);
+        let mut files = Vec::new();
+        for upload in uploads {
+            let name = get_str(upload, "name");
+            let reference = upload.get("reference_id").unwrap_or(&Value::Null);
+            let local = locate(&reference.to_string(), &upload_path(upload, path));
+            files.push(ArchiveFile {
+                name: name.to_string(),
+                size: upload.get("size").and_then(|x| x.as_u64()).unwrap_or(0),
+                local: local.map(|x| {
+                    let file_name = Path::new(&x).file_name().unwrap_or_default();
+                    let entry = entry_name(&file_name.to_string_lossy(), &mut used);
+                    (x, entry)
+                }),
+            });
+        }
+        if uploads.is_empty() && is_video(element) {
+            let local = video::local_path(element, path);
+            let name = match &local {
+                Some(x) => Path::new(x).file_name().unwrap_or_default(),
+                None => Path::new(get_str(element, "title")).as_os_str(),
+            };
+            let name = name.to_string_lossy().to_string();
+            files.push(ArchiveFile {
+                size: local
+                    .as_ref()
+                    .and_then(|x| std::fs::metadata(x).ok())
+                    .map(|x| x.len())
+                    .unwrap_or(0),
+                local: local.map(|x| (x, entry_name(&name, &mut used))),
+                name,
+            });
+        }
+        let data = element.get("data").unwrap_or(&Value::Null);
+        let description = match get_str(data, "description") {
+            "" => get_str(element, "description"),
+            v => v,
+        };
+        items.push(Item {
+            title: get_str(element, "title").to_string(),
+            kind: kind_name(get_str(element, "type")).to_string(),
+            start: time(element, "start_time"),
+            end: time(element, "end_time"),
+            description: to_text(description),
+            files,
+        });
+    }
+    items
+}
+
+fn link(entry: &str) -> String {
+    entry
+        .split('/')
+        .map(|x| urlencoding::encode(x).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 文件名中的 `[`、`]` 等会破坏 Markdown 的链接，加上反斜杠
+fn escape_markdown(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+fn dates(item: &Item) -> String {
+    let mut ret = vec![format!("类型：{}", item.kind)];
+    if !item.start.is_empty() {
+        ret.push(format!("开始：{}", item.start));
+    }
+    if !item.end.is_empty() {
+        ret.push(format!("截止：{}", item.end));
+    }
+    ret.join("　")
+}
+
+fn markdown(course: &Course, items: &[Item]) -> String {
+    let mut lines = vec![
+        format!("# {}", course.name),
+        String::new(),
+        format!("教师：{}　学期：{}", course.instructors, course.semester),
+        format!("归档时间：{}", now().format("%Y-%m-%d %H:%M")),
+    ];
+    for (i, item) in items.iter().enumerate() {
+        lines.push(String::new());
+        lines.push(format!("## {}. {}", i + 1, item.title));
+        lines.push(String::new());
+        lines.push(dates(item));
+        if !item.description.is_empty() {
+            lines.push(String::new());
+            lines.push(item.description.clone());
+        }
+        if !item.files.is_empty() {
+            lines.push(String::new());
+        }
+        for file in item.files.iter() {
+            lines.push(match &file.local {
+                Some((_, entry)) => format!(
+                    "- [{}]({}) {}",
+                    escape_markdown(&file.name),
+                    link(entry),
+                    format_size(file.size)
+                ),
+                None => format!("- {}（未下载）", escape_markdown(&file.name)),
+            });
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn html(course: &Course, items: &[Item]) -> String {
+    let mut body = vec![
+        format!("<h1>{}</h1>", escape(&course.name)),
+        format!(
+            "<p>教师：{}　学期：{}<br>归档时间：{}</p>",
+            escape(&course.instructors),
+            escape(&course.semester),
+            now().format("%Y-%m-%d %H:%M")
+        ),
+    ];
+    for (i, item) in items.iter().enumerate() {
+        body.push(format!("<h2>{}. {}</h2>", i + 1, escape(&item.title)));
+        body.push(format!("<p class=\"meta\">{}</p>", escape(&dates(item))));
+        if !item.description.is_empty() {
+            body.push(format!(
+                "<p>{}</p>",
+                escape(&item.description).replace('\n', "<br>")
+            ));
+        }
+        if item.files.is_empty() {
+            continue;
+        }
+        body.push("<ul>".to_string());
+        for file in item.files.iter() {
+            body.push(match &file.local {
+                Some((_, entry)) => format!(
+                    "<li><a href=\"{}\">{}</a> {}</li>",
+                    link(entry),
+                    escape(&file.name),
+                    format_size(file.size)
+                ),
+                None => format!("<li>{}（未下载）</li>", escape(&file.name)),
+            });
+        }
+        body.push("</ul>".to_string());
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body{{max-width:50em;margin:auto;font-family:sans-serif}}.meta{{color:#666}}</style>\n\
+         </head>\n<body>\n{}\n</body>\n</html>\n",
+        escape(&course.name),
+        body.join("\n")
+    )
+}
+
+fn write_zip(file: &str, course: &Course, items: &[Item]) -> anyhow::Result<()> {
+    if let Some(parent) = Path::new(file).parent() {
+        create_dir_all(parent)?;
+    }
+    let mut zip = ZipWriter::new(File::create(file)?);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("index.html", deflated)?;
+    zip.write_all(html(course, items).as_bytes())?;
+    zip.start_file("index.md", deflated)?;
+    zip.write_all(markdown(course, items).as_bytes())?;
+    for (local, entry) in items
+        .iter()
+        .flat_map(|x| x.files.iter())
+        .filter_map(|x| x.local.as_ref())
+    {
+        let ext = Path::new(local)
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let method = match STORED.contains(&ext.as_str()) {
+            true => CompressionMethod::Stored,
+            false => CompressionMethod::Deflated,
+        };
+        let size = std::fs::metadata(local).map(|x| x.len()).unwrap_or(0);
+        let options = SimpleFileOptions::default()
+            .compression_method(method)
+            .large_file(size >= u32::MAX as u64);
+        zip.start_file(entry.as_str(), options)?;
+        copy(&mut File::open(local)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// 把课程中已经下载的文件和活动目录打包，返回压缩包的位置
+pub fn archive(course: &Course, cookie: &str) -> Result<String, Error> {
+    let elements = get_activities(&course.id, cookie)?;
+    let items = collect(&elements, &download_path());
+    let files = items.iter().flat_map(|x| x.files.iter());
+    let missing = files.clone().filter(|x| x.local.is_none()).count();
+    let total = files.clone().count();
+    let file = format!("{}{}.zip", EXPORT_PATH, sanitize(&course.name));
+    info!("正在打包 {}，共 {} 个文件", course.name, total - missing);
+    if let Err(e) = write_zip(&file, course, &items) {
+        warn!("打包失败 {} {}", file, e);
+        return Err(Error::Write);
+    }
+    if missing > 0 {
+        warn!("有 {} 个文件还没有下载，可以先下载或同步这门课程", missing);
+    }
+    info!("已归档到 {}", file);
+    Ok(file)
+}
+
+pub fn main() {
+    let courses = get_cookie().and_then(|x| select_courses(&x).map(|c| (x, c)));
+    let (cookie, courses) = match courses {
+        Ok(v) => v,
+        Err(e) => return e.logger(),
+    };
+    for course in courses {
+        if let Err(e) = archive(&course, &cookie) {
+            e.logger();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_link_text() {
+        assert_eq!(escape_markdown("第1章.pdf"), "第1章.pdf");
+        assert_eq!(escape_markdown("[2024]试卷.pdf"), "\\[2024\\]试卷.pdf");
+        assert_eq!(escape_markdown("a_b*c`d\\e"), "a\\_b\\*c\\`d\\\\e");
+    }
+
+    #[test]
+    fn link_is_percent_encoded() {
+        assert_eq!(link("files/a b(1).pdf"), "files/a%20b%281%29.pdf");
+    }
+}
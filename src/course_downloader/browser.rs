@@ -0,0 +1,149 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::MultiSelect;
+use serde_json::Value;
+
+use super::filter::Filter;
+use super::video::is_video;
+use crate::public::progress::format_size;
+use crate::public::VOID_VEC;
+
+/// 课程内容树中的一个活动及其下要下载的文件
+#[derive(Debug, Clone)]
+pub struct Node<'a> {
+    pub activity: &'a Value,
+    pub files: Vec<&'a Value>,
+    pub video: bool,
+}
+
+fn get_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value
+        .get(key)
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("")
+}
+
+pub fn file_size(file: &Value) -> u64 {
+    file.get("size").and_then(|x| x.as_u64()).unwrap_or(0)
+}
+
+impl Node<'_> {
+    pub fn title(&self) -> &str {
+        get_str(self.activity, "title")
+    }
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|x| file_size(x)).sum()
+    }
+    fn label(&self) -> String {
+        match self.video {
+            true => format!("▸ {}（视频）", self.title()),
+            false => format!(
+                "▸ {}（{} 个文件 {}）",
+                self.title(),
+                self.files.len(),
+                format_size(self.size())
+            ),
+        }
+    }
+}
+
+fn file_label(file: &Value, last: bool) -> String {
+    format!(
+        "    {} {}  {}",
+        match last {
+            true => "└",
+            false => "├",
+        },
+        get_str(file, "name"),
+        format_size(file_size(file))
+    )
+}
+
+/// 按课程中的顺序把活动整理成树，只保留满足过滤条件的内容
+pub fn build<'a>(elements: &'a [Value], filter: &Filter) -> Vec<Node<'a>> {
+    let mut nodes = Vec::new();
+    for element in elements {
+        if !filter.match_activity(element) {
+            continue;
+        }
+        let uploads = element
+            .get("uploads")
+            .unwrap_or(&Value::Null)
+            .as_array()
+            .unwrap_or(&*VOID_VEC);
+        if uploads.is_empty() && is_video(element) {
+            if filter.match_title(get_str(element, "title")) {
+                nodes.push(Node {
+                    activity: element,
+                    files: Vec::new(),
+                    video: true,
+                });
+            }
+            continue;
+        }
+        let files = uploads
+            .iter()
+            .filter(|x| filter.match_upload(x))
+            .collect::<Vec<_>>();
+        if !files.is_empty() {
+            nodes.push(Node {
+                activity: element,
+                files,
+                video: false,
+            });
+        }
+    }
+    nodes
+}
+
+pub fn print(nodes: &[Node]) {
+    for node in nodes {
+        println!("{}", node.label());
+        for (i, file) in node.files.iter().enumerate() {
+            println!("{}", file_label(file, i + 1 == node.files.len()));
+        }
+    }
+    println!(
+        "共 {} 个文件 {}，{} 个视频",
+        nodes.iter().map(|x| x.files.len()).sum::<usize>(),
+        format_size(nodes.iter().map(|x| x.size()).sum()),
+        nodes.iter().filter(|x| x.video).count()
+    );
+}
+
+/// 多选活动或单个文件，选中活动即选中其下全部文件
+pub fn select<'a>(nodes: &[Node<'a>]) -> Vec<Node<'a>> {
+    let mut items = Vec::new();
+    let mut index = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        items.push(node.label());
+        index.push((i, None));
+        for (j, file) in node.files.iter().enumerate() {
+            items.push(file_label(file, j + 1 == node.files.len()));
+            index.push((i, Some(j)));
+        }
+    }
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("选择要下载的内容（空格选择，回车确认）")
+        .items(&items)
+        .interact()
+        .unwrap_or_default();
+
+    let mut selected: Vec<Option<Node>> = vec![None; nodes.len()];
+    for (i, file) in chosen.into_iter().map(|x| index[x]) {
+        let node = &nodes[i];
+        match file {
+            None => selected[i] = Some(node.clone()),
+            Some(j) => {
+                let entry = selected[i].get_or_insert_with(|| Node {
+                    files: Vec::new(),
+                    ..node.clone()
+                });
+                if !entry.files.iter().any(|x| std::ptr::eq(*x, node.files[j])) {
+                    entry.files.push(node.files[j]);
+                }
+            }
+        }
+    }
+    selected.into_iter().flatten().collect()
+}
@@ -0,0 +1,198 @@
+use super::download::get_with_cookie;
+use super::main::Error;
+use anyhow::Result;
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::login::main::get_session;
+use crate::login::profile;
+use crate::public::dedup;
+use crate::public::manifest::{self, Action};
+use crate::public::storage::{read_json, write_json};
+use crate::public::DownloadFile;
+use crate::public::VOID_VEC;
+
+const COURSE_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Course {
+    pub id: String,
+    pub name: String,
+    pub instructors: String,
+    pub semester: String,
+}
+
+impl Course {
+    pub fn from_json(element: &Value) -> Self {
+        let id = element.get("id").unwrap_or(&Value::Null).to_string();
+        let name = element
+            .get("name")
+            .unwrap_or(&Value::Null)
+            .as_str()
+            .unwrap_or("");
+        let instructors = element
+            .get("instructors")
+            .unwrap_or(&Value::Null)
+            .as_array()
+            .unwrap_or(&*VOID_VEC)
+            .iter()
+            .map(|x| x.get("name").unwrap_or(&Value::Null).as_str().unwrap_or(""))
+            .collect::<Vec<_>>()
+            .join(",");
+        let semester = element
+            .get("semester")
+            .unwrap_or(&Value::Null)
+            .get("name")
+            .unwrap_or(&Value::Null)
+            .as_str()
+            .unwrap_or("");
+        Self {
+            id,
+            name: name.to_string(),
+            instructors,
+            semester: semester.to_string(),
+        }
+    }
+}
+
+pub fn get_cookie() -> Result<String, Error> {
+    match get_session() {
+        Some(v) => Ok(format!("session={}", v)),
+        None => Err(Error::LoginDataInvalid),
+    }
+}
+
+/// 获取所有已选课程
+pub fn get_courses(cookie: &str) -> Result<Vec<Course>, Error> {
+    let mut page = 1;
+    let mut courses = Vec::new();
+    loop {
+        let resp = get_with_cookie(format!("https://lnt.xmu.edu.cn/api/my-courses?&page={}&page_size={}&showScorePassedStatus=false",page,COURSE_PAGE_SIZE), cookie)?;
+        let json: Value = match resp.json() {
+            Ok(v) => v,
+            Err(_) => return Err(Error::LoginDataInvalid),
+        };
+        trace!("课程列表 json = {}", &json);
+        let elements = json
+            .get("courses")
+            .unwrap_or(&Value::Null)
+            .as_array()
+            .unwrap_or(&*VOID_VEC);
+        courses.extend(elements.iter().map(Course::from_json));
+        let pages = json
+            .get("pages")
+            .unwrap_or(&Value::Null)
+            .as_u64()
+            .unwrap_or(1) as usize;
+        if elements.len() < COURSE_PAGE_SIZE || page >= pages {
+            break;
+        }
+        page += 1;
+    }
+    debug!("获取到 {} 门课程", courses.len());
+    Ok(courses)
+}
+
+fn cache_path() -> String {
+    format!("{}courses.json", profile::data_path())
+}
+
+/// 优先读取本地缓存的课程列表，`refresh` 为真或没有缓存时重新获取
+pub fn load_courses(cookie: &str, refresh: bool) -> Result<Vec<Course>, Error> {
+    if !refresh {
+        let cached: Option<Vec<Course>> =
+            read_json(&cache_path()).and_then(|x| serde_json::from_value(x).ok());
+        if let Some(courses) = cached.filter(|x| !x.is_empty()) {
+            debug!("从缓存读取 {} 门课程", courses.len());
+            return Ok(courses);
+        }
+    }
+    let courses = get_courses(cookie)?;
+    let ret = serde_json::to_value(&courses)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&cache_path(), &x));
+    if let Err(e) = ret {
+        warn!("缓存课程列表失败 {}", e);
+    }
+    Ok(courses)
+}
+
+/// 获取课程下的所有活动
+pub fn get_activities(course_id: &str, cookie: &str) -> Result<Vec<Value>, Error> {
+    let resp = get_with_cookie(
+        format!(
+            "https://lnt.xmu.edu.cn/api/courses/{}/activities",
+            course_id
+        ),
+        cookie,
+    )?;
+    let json: Value = match resp.json() {
+        Ok(v) => v,
+        Err(_) => return Err(Error::LoginDataInvalid),
+    };
+    trace!("课程json = {}", json);
+    Ok(json
+        .get("activities")
+        .unwrap_or(&Value::Null)
+        .as_array()
+        .unwrap_or(&*VOID_VEC)
+        .to_owned())
+}
+
+pub fn get_upload_url(reference_id: &str, cookie: &str) -> Result<String, Error> {
+    let resp = get_with_cookie(
+        format!("https://lnt.xmu.edu.cn/api/uploads/reference/{reference_id}/url"),
+        cookie,
+    )?;
+    let json: Value = resp.json().unwrap_or(Value::Null);
+    trace!("获取到 json = {}", json);
+    Ok(json
+        .get("url")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("")
+        .to_string())
+}
+
+/// upload 下载后保存的位置
+pub fn upload_path(file: &Value, path: &str) -> String {
+    let name = file
+        .get("name")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("");
+    format!("{}/{}", path, name)
+}
+
+/// 把一个 upload 放入下载队列，返回最终保存的位置，跳过时返回 None。
+/// `sync` 为真时本地相同的文件总是跳过
+pub fn queue_upload(
+    file: &Value,
+    cookie: &str,
+    path: &str,
+    sync: bool,
+) -> Result<Option<String>, Error> {
+    trace!("获取到一个 upload file = {}", file);
+    let reference_id = file.get("reference_id").unwrap_or(&Value::Null).to_string();
+    debug!("获取到 reference_id = {}", reference_id);
+    let size = file.get("size").and_then(|x| x.as_u64()).unwrap_or(0);
+    let file = upload_path(file, path);
+    if dedup::link_reference(&reference_id, size, &file) {
+        let linked = DownloadFile::new("", &file).with_reference(&reference_id, size);
+        manifest::record(&linked, Action::New, None);
+        return Ok(Some(file));
+    }
+    let mut d = DownloadFile::new("", &file).with_reference(&reference_id, size);
+    if sync {
+        d = d.for_sync();
+    }
+    // 先在本地判断是否需要下载，需要时才请求下载地址
+    let mut d = match d.plan() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    d.url = get_upload_url(&reference_id, cookie)?;
+    debug!("保存到 {}", d.file);
+    Ok(d.queue())
+}
@@ -1,16 +1,35 @@
 use super::main::Error;
 use anyhow::Result;
-use reqwest::blocking::{Client, Response};
 use reqwest::header::COOKIE;
+use reqwest::multipart::{Form, Part};
 use reqwest::IntoUrl;
+use serde_json::Value;
+
+use crate::public::http::{client, send, send_upload, Response};
 
 pub fn get_with_cookie<U: IntoUrl>(url: U, cookie: &str) -> Result<Response, Error> {
-    match Client::new()
-        .get(url)
-        .header(COOKIE, cookie)
-        .header("User-Agent","Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
-        .send(){
-            Ok(e)=>Ok(e),
-            Err(_)=>Err(Error::NetworkFailure),
-        }
+    match send(client().get(url).header(COOKIE, cookie)) {
+        Ok(e) => Ok(e),
+        Err(_) => Err(Error::NetworkFailure),
+    }
+}
+
+pub fn post_json_with_cookie<U: IntoUrl>(
+    url: U,
+    data: &Value,
+    cookie: &str,
+) -> Result<Response, Error> {
+    match send(client().post(url).header(COOKIE, cookie).json(data)) {
+        Ok(e) => Ok(e),
+        Err(_) => Err(Error::NetworkFailure),
+    }
+}
+
+/// 以 multipart 形式上传文件，大文件上传时间不定，只有连接超时
+pub fn upload_with_cookie<U: IntoUrl>(url: U, part: Part, cookie: &str) -> Result<Response, Error> {
+    let form = Form::new().part("file", part);
+    match send_upload(client().put(url).header(COOKIE, cookie).multipart(form)) {
+        Ok(e) => Ok(e),
+        Err(_) => Err(Error::NetworkFailure),
+    }
 }
@@ -0,0 +1,369 @@
+use chrono::NaiveDate;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::login::profile;
+use crate::public::ical::parse_rfc3339;
+use crate::public::storage::{read_json, write_json};
+
+const DEFAULT_KEY: &str = "default";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Filter {
+    pub include_ext: Vec<String>,
+    pub exclude_ext: Vec<String>,
+    /// 以 `re:` 开头为正则表达式，否则为通配符
+    pub name_pattern: String,
+    pub max_size_mb: Option<f64>,
+    pub activity_types: Vec<String>,
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    /// 编译好的 `name_pattern`，第一次匹配时生成
+    #[serde(skip)]
+    name_regex: OnceLock<Option<Regex>>,
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut ret = String::from("(?i)^");
+    for c in glob.chars() {
+        match c {
+            '*' => ret.push_str(".*"),
+            '?' => ret.push('.'),
+            c => ret.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    ret.push('$');
+    ret
+}
+
+fn split_list(text: &str) -> Vec<String> {
+    text.split([',', '，', ' '])
+        .map(|x| x.trim().trim_start_matches('.').to_lowercase())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.include_ext.is_empty()
+            && self.exclude_ext.is_empty()
+            && self.name_pattern.is_empty()
+            && self.max_size_mb.is_none()
+            && self.activity_types.is_empty()
+            && self.after.is_none()
+            && self.before.is_none()
+    }
+
+    fn name_regex(&self) -> Option<&Regex> {
+        self.name_regex
+            .get_or_init(|| self.compile_pattern())
+            .as_ref()
+    }
+
+    fn compile_pattern(&self) -> Option<Regex> {
+        if self.name_pattern.is_empty() {
+            return None;
+        }
+        let pattern = match self.name_pattern.strip_prefix("re:") {
+            Some(re) => re.to_string(),
+            None => glob_to_regex(&self.name_pattern),
+        };
+        match Regex::new(&pattern) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                warn!("文件名规则 {} 无效：{}", self.name_pattern, e);
+                None
+            }
+        }
+    }
+
+    pub fn match_activity(&self, element: &Value) -> bool {
+        let kind = element
+            .get("type")
+            .unwrap_or(&Value::Null)
+            .as_str()
+            .unwrap_or("");
+        if !self.activity_types.is_empty() && !self.activity_types.iter().any(|x| x == kind) {
+            return false;
+        }
+        let date = ["start_time", "created_at"]
+            .iter()
+            .find_map(|k| element.get(*k).and_then(|x| x.as_str()))
+            .and_then(parse_rfc3339)
+            .map(|x| x.date());
+        match date {
+            Some(date) => {
+                self.after.map(|x| date >= x).unwrap_or(true)
+                    && self.before.map(|x| date <= x).unwrap_or(true)
+            }
+            None => true,
+        }
+    }
+
+    /// 视频活动没有文件名，只用标题匹配通配符或正则
+    pub fn match_title(&self, title: &str) -> bool {
+        match self.name_regex() {
+            Some(re) => re.is_match(title),
+            None => true,
+        }
+    }
+
+    fn match_name(&self, name: &str) -> bool {
+        let ext = match name.rsplit_once('.') {
+            Some((_, ext)) => ext.to_lowercase(),
+            None => String::new(),
+        };
+        if !self.include_ext.is_empty() && !self.include_ext.contains(&ext) {
+            return false;
+        }
+        if self.exclude_ext.contains(&ext) {
+            return false;
+        }
+        self.match_title(name)
+    }
+
+    pub fn match_upload(&self, file: &Value) -> bool {
+        let name = file
+            .get("name")
+            .unwrap_or(&Value::Null)
+            .as_str()
+            .unwrap_or("");
+        let size = file.get("size").and_then(|x| x.as_u64());
+        let size_ok = match (self.max_size_mb, size) {
+            (Some(max), Some(size)) => size as f64 <= max * 1024.0 * 1024.0,
+            _ => true,
+        };
+        size_ok && self.match_name(name)
+    }
+
+    fn describe(&self) -> String {
+        if self.is_empty() {
+            return "不过滤".to_string();
+        }
+        let mut ret = Vec::new();
+        if !self.include_ext.is_empty() {
+            ret.push(format!("只要 {}", self.include_ext.join(",")));
+        }
+        if !self.exclude_ext.is_empty() {
+            ret.push(format!("排除 {}", self.exclude_ext.join(",")));
+        }
+        if !self.name_pattern.is_empty() {
+            ret.push(format!("文件名 {}", self.name_pattern));
+        }
+        if let Some(max) = self.max_size_mb {
+            ret.push(format!("不超过 {}MB", max));
+        }
+        if !self.activity_types.is_empty() {
+            ret.push(format!("活动类型 {}", self.activity_types.join(",")));
+        }
+        if let Some(after) = self.after {
+            ret.push(format!("{} 之后", after));
+        }
+        if let Some(before) = self.before {
+            ret.push(format!("{} 之前", before));
+        }
+        ret.join("；")
+    }
+}
+
+fn filters_path() -> String {
+    format!("{}filters.json", profile::data_path())
+}
+
+fn load_filters() -> HashMap<String, Filter> {
+    read_json(&filters_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+fn save_filter(key: &str, filter: &Filter) {
+    let mut filters = load_filters();
+    filters.insert(key.to_string(), filter.clone());
+    let ret = serde_json::to_value(&filters)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&filters_path(), &x));
+    match ret {
+        Ok(_) => info!("已保存过滤条件"),
+        Err(e) => warn!("保存过滤条件失败 {}", e),
+    }
+}
+
+fn input(prompt: &str, default: String) -> String {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_default()
+}
+
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    match text.trim() {
+        "" => None,
+        text => match NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            Ok(d) => Some(d),
+            Err(_) => {
+                warn!("日期 {} 格式不正确，已忽略", text);
+                None
+            }
+        },
+    }
+}
+
+fn edit(filter: &Filter) -> Filter {
+    let opt_to_string = |x: Option<String>| x.unwrap_or_default();
+    Filter {
+        include_ext: split_list(&input(
+            "只下载这些扩展名（逗号分隔，留空不限）",
+            filter.include_ext.join(","),
+        )),
+        exclude_ext: split_list(&input(
+            "排除这些扩展名（逗号分隔）",
+            filter.exclude_ext.join(","),
+        )),
+        name_pattern: input(
+            "文件名通配符，或以 re: 开头的正则",
+            filter.name_pattern.clone(),
+        )
+        .trim()
+        .to_string(),
+        max_size_mb: input(
+            "最大文件大小（MB，留空不限）",
+            opt_to_string(filter.max_size_mb.map(|x| x.to_string())),
+        )
+        .trim()
+        .parse()
+        .ok(),
+        activity_types: split_list(&input(
+            "活动类型（如 material,homework,online_video，留空不限）",
+            filter.activity_types.join(","),
+        )),
+        after: parse_date(&input(
+            "开始日期 YYYY-MM-DD（留空不限）",
+            opt_to_string(filter.after.map(|x| x.to_string())),
+        )),
+        before: parse_date(&input(
+            "结束日期 YYYY-MM-DD（留空不限）",
+            opt_to_string(filter.before.map(|x| x.to_string())),
+        )),
+        name_regex: OnceLock::new(),
+    }
+}
+
+/// 保存的过滤条件，课程自己的优先，其次是默认的
+pub fn saved(course_id: &str) -> Filter {
+    let filters = load_filters();
+    filters
+        .get(course_id)
+        .or(filters.get(DEFAULT_KEY))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 选择本次下载使用的过滤条件
+pub fn choose(course_id: &str) -> Filter {
+    let saved = saved(course_id);
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("过滤条件")
+        .default(0)
+        .item(format!("使用保存的条件：{}", saved.describe()))
+        .item("不过滤")
+        .item("编辑过滤条件")
+        .interact()
+        .unwrap_or(0);
+    let filter = match selection {
+        0 => saved,
+        1 => Filter::default(),
+        _ => {
+            let filter = edit(&saved);
+            let save = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("保存过滤条件")
+                .default(0)
+                .item("仅本次使用")
+                .item("保存到这门课程")
+                .item("保存为默认")
+                .interact()
+                .unwrap_or(0);
+            match save {
+                1 => save_filter(course_id, &filter),
+                2 => save_filter(DEFAULT_KEY, &filter),
+                _ => {}
+            }
+            filter
+        }
+    };
+    debug!("使用过滤条件 {:?}", filter);
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(include: &str, exclude: &str, pattern: &str) -> Filter {
+        Filter {
+            include_ext: split_list(include),
+            exclude_ext: split_list(exclude),
+            name_pattern: pattern.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn glob_pattern() {
+        let f = filter("", "", "第?章*");
+        assert!(f.match_name("第1章 绪论.pdf"));
+        assert!(f.match_name("第2章.PPTX"));
+        assert!(!f.match_name("附录.pdf"));
+        assert!(!f.match_name("第10章.pdf"));
+        // 通配符以外的字符按原样匹配
+        assert!(filter("", "", "a.b*").match_name("a.b.pdf"));
+        assert!(!filter("", "", "a.b*").match_name("axb.pdf"));
+    }
+
+    #[test]
+    fn regex_pattern() {
+        let f = filter("", "", r"re:^Lab\d+");
+        assert!(f.match_name("Lab3.zip"));
+        assert!(!f.match_name("lab3.zip"));
+        // 无效的正则不过滤
+        assert!(filter("", "", "re:(").match_name("a.pdf"));
+    }
+
+    #[test]
+    fn extensions() {
+        let f = filter(".PDF，docx", "", "");
+        assert_eq!(f.include_ext, vec!["pdf", "docx"]);
+        assert!(f.match_name("a.Pdf"));
+        assert!(!f.match_name("a.pptx"));
+        assert!(!f.match_name("README"));
+        let f = filter("", "mp4 zip", "");
+        assert!(f.match_name("a.pdf"));
+        assert!(!f.match_name("a.MP4"));
+    }
+
+    #[test]
+    fn videos_ignore_extensions() {
+        let f = filter("pdf", "", "");
+        assert!(f.match_title("第一讲 录播"));
+        assert!(!filter("pdf", "", "实验*").match_title("第一讲 录播"));
+    }
+
+    #[test]
+    fn upload_size() {
+        let f = Filter {
+            max_size_mb: Some(1.0),
+            ..Default::default()
+        };
+        assert!(f.match_upload(&json!({"name": "a.pdf", "size": 1024})));
+        assert!(!f.match_upload(&json!({"name": "a.pdf", "size": 2 * 1024 * 1024})));
+        assert!(f.match_upload(&json!({"name": "a.pdf"})));
+    }
+}
@@ -1,20 +1,23 @@
-use super::download::get_with_cookie;
+use super::browser;
+use super::course::{get_activities, queue_upload};
+use super::filter;
+use super::search::select_courses;
+use super::video::{self, queue_best_video, queue_video};
 use anyhow::Result;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Select;
-use log::info;
-use log::{debug, trace, LevelFilter};
-use serde_json::Value;
+use log::LevelFilter;
+use log::{debug, info};
 
 use crate::login::main::get_session;
+use crate::login::profile;
 use crate::public::logger::Logger;
 use crate::public::logger::LoggerData;
-use crate::public::DownloadFile;
-use crate::public::VOID_VEC;
 
-const DOWNLOAD_PATH: &str = "./download/";
-const PAGE_SIZE: usize = 5;
-const END_PAGE: usize = PAGE_SIZE + 1;
+/// 当前账号的下载目录
+pub fn download_path() -> String {
+    profile::current().download_path
+}
 
 #[derive(Debug)]
 pub enum Error {
@@ -35,142 +38,108 @@ impl Logger for Error {
 
 pub fn main() {
     let cookie = get_session();
-    let course_id = match get_course_id(&cookie) {
+    let courses = match &cookie {
+        Some(v) => select_courses(&format!("session={}", v)),
+        None => Err(Error::LoginDataInvalid),
+    };
+    let courses = match courses {
         Ok(v) => v,
         Err(e) => {
             e.logger();
             return;
         }
     };
-    info!("获取到 course_id = {}", course_id);
-    match get_file(&course_id, &cookie) {
-        Ok(_) => {}
-        Err(e) => e.logger(),
+    for course in courses {
+        info!("获取到 course_id = {} {}", course.id, course.name);
+        match get_file(&course.id, &cookie) {
+            Ok(_) => {}
+            Err(e) => e.logger(),
+        }
     }
 }
 
-fn get_course_id(cookie: &Option<String>) -> Result<String, Error> {
+fn get_file(course_id: &str, cookie: &Option<String>) -> Result<(), Error> {
     let cookie = match cookie {
         Some(v) => format!("session={}", v),
         None => return Err(Error::LoginDataInvalid),
     };
-    let mut page = 1;
-    loop {
-        let resp = get_with_cookie(format!("https://lnt.xmu.edu.cn/api/my-courses?&page={}&page_size={}&showScorePassedStatus=false",page,PAGE_SIZE), &cookie)?;
-        let json: Value = match resp.json() {
-            Ok(v) => v,
-            Err(_) => return Err(Error::LoginDataInvalid),
-        };
-        trace!("课程列表 json = {}", &json);
-        let mut choices = Vec::new();
-        choices.push("上一页".to_string());
-        let elements = json
-            .get("courses")
-            .unwrap_or(&Value::Null)
-            .as_array()
-            .unwrap_or(&*VOID_VEC);
-        for element in elements {
-            let name = element
-                .get("name")
-                .unwrap_or(&Value::Null)
-                .as_str()
-                .unwrap_or("");
-            let instructor = element
-                .get("instructors")
-                .unwrap_or(&Value::Null)
-                .as_array()
-                .unwrap_or(&*VOID_VEC)
-                .to_owned()
-                .iter()
-                .map(|x| x.get("name").unwrap_or(&Value::Null).as_str().unwrap_or(""))
-                .collect::<Vec<_>>()
-                .join(",");
-            let semester = element
-                .get("semester")
-                .unwrap_or(&Value::Null)
-                .get("name")
-                .unwrap_or(&Value::Null)
-                .as_str()
-                .unwrap_or("");
-            choices.push(format!("{} {} {}", name, instructor, semester));
+    let elements = get_activities(course_id, &cookie)?;
+    let filter = filter::choose(course_id);
+    let mut nodes = browser::build(&elements, &filter);
+    browser::print(&nodes);
+    if nodes.is_empty() {
+        return Ok(());
+    }
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("下载哪些内容")
+        .default(0)
+        .item("全部下载")
+        .item("选择要下载的内容")
+        .item("取消")
+        .interact()
+        .unwrap_or(2);
+    match selection {
+        0 => {}
+        1 => {
+            nodes = browser::select(&nodes);
+            browser::print(&nodes);
         }
-        choices.push("下一页".to_string());
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("请选择要下载的课程")
-            .items(&choices)
-            .interact()
-            .unwrap_or(0);
-        match selection {
-            0 => {
-                if page >= 1 {
-                    page -= 1;
-                }
-            }
-            1..=PAGE_SIZE => {
-                return match elements[selection - 1].get("id") {
-                    Some(v) => Ok(v.to_string()),
-                    None => Err(Error::LoginDataInvalid),
-                };
+        _ => return Ok(()),
+    }
+
+    for node in nodes {
+        if node.video {
+            queue_video(node.activity, &cookie, &download_path())?;
+        }
+        for file in node.files {
+            queue_upload(file, &cookie, &download_path(), false)?;
+        }
+    }
+    Ok(())
+}
+
+/// 不经过交互，按保存的过滤条件下载整门课程，返回放入队列的数量
+pub fn queue_course(course_id: &str, cookie: &str) -> Result<usize, Error> {
+    let elements = get_activities(course_id, cookie)?;
+    let nodes = browser::build(&elements, &filter::saved(course_id));
+    let mut count = 0;
+    for node in nodes {
+        if node.video && queue_best_video(node.activity, cookie, &download_path())? {
+            count += 1;
+        }
+        for file in node.files {
+            if queue_upload(file, cookie, &download_path(), false)?.is_some() {
+                count += 1;
             }
-            END_PAGE => page += 1,
-            _ => {}
         }
     }
+    Ok(count)
 }
 
-fn get_file(course_id: &str, cookie: &Option<String>) -> Result<(), Error> {
-    let cookie = match cookie {
-        Some(v) => format!("session={}", v),
-        None => return Err(Error::LoginDataInvalid),
-    };
-    let resp = get_with_cookie(
-        format!(
-            "https://lnt.xmu.edu.cn/api/courses/{}/activities",
-            course_id
-        ),
-        &cookie,
-    )?;
-    let json: Value = match resp.json() {
-        Ok(v) => v,
-        Err(_) => return Err(Error::LoginDataInvalid),
-    };
-    trace!("课程json = {}", json);
-    let elements = json
-        .get("activities")
-        .unwrap_or(&Value::Null)
-        .as_array()
-        .unwrap_or(&*VOID_VEC);
-    for element in elements {
-        let uploads = element
-            .get("uploads")
-            .unwrap_or(&Value::Null)
-            .as_array()
-            .unwrap_or(&*VOID_VEC);
-        for file in uploads {
-            trace!("获取到一个 upload file = {}", file);
-            let reference_id = file.get("reference_id").unwrap_or(&Value::Null).to_string();
-            debug!("获取到 reference_id = {}", reference_id);
-            let name = file
-                .get("name")
-                .unwrap_or(&Value::Null)
-                .as_str()
-                .unwrap_or("");
-            debug!("获取到 name = {}", name);
-            let resp = get_with_cookie(
-                format!("https://lnt.xmu.edu.cn/api/uploads/reference/{reference_id}/url"),
-                &cookie,
-            )?;
-            let json: Value = resp.json().unwrap_or(Value::Null);
-            trace!("获取到 json = {}", json);
-            let url = json
-                .get("url")
-                .unwrap_or(&Value::Null)
-                .as_str()
-                .unwrap_or("");
-            let file = format!("{}/{}", DOWNLOAD_PATH, name);
-            let d = DownloadFile::new(url, &file);
-            d.run();
+/// 同 `queue_course`，但跳过本地已经存在的文件，返回新放入队列的文件
+pub fn sync_course(course_id: &str, cookie: &str) -> Result<Vec<String>, Error> {
+    let path = download_path();
+    let elements = get_activities(course_id, cookie)?;
+    let nodes = browser::build(&elements, &filter::saved(course_id));
+    let mut queued = Vec::new();
+    for node in nodes {
+        if node.video {
+            let title = node.activity.get("title").and_then(|x| x.as_str());
+            match video::is_downloaded(node.activity, &path) {
+                true => debug!("视频已存在，跳过 {:?}", title),
+                false => {
+                    if queue_best_video(node.activity, cookie, &path)? {
+                        queued.push(title.unwrap_or("").to_string());
+                    }
+                }
+            }
+        }
+        // 本地相同的文件总是跳过，不同的按文件名冲突的设置更新
+        for file in node.files {
+            if let Some(target) = queue_upload(file, cookie, &path, true)? {
+                queued.push(target);
+            }
         }
     }
-    Ok(())
+    Ok(queued)
 }
@@ -1,3 +1,8 @@
+pub mod browser;
+pub mod course;
 pub mod download;
+pub mod filter;
 pub mod main;
+pub mod search;
+pub mod video;
 pub use main::main;
@@ -0,0 +1,203 @@
+use super::course::{load_courses, Course};
+use super::main::Error;
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, MultiSelect, Select};
+use log::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sort {
+    Semester,
+    Name,
+    Instructor,
+}
+
+impl Sort {
+    fn name(&self) -> &'static str {
+        match *self {
+            Sort::Semester => "按学期",
+            Sort::Name => "按课程名",
+            Sort::Instructor => "按教师",
+        }
+    }
+}
+
+/// 子序列模糊匹配，连续命中和开头命中得分更高，不匹配返回 None
+fn fuzzy_score(text: &str, pattern: &str) -> Option<i64> {
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+    for c in pattern.to_lowercase().chars() {
+        let found = text[pos..].iter().position(|x| *x == c)? + pos;
+        score += match (found, last) {
+            (0, _) => 3,
+            (f, Some(l)) if f == l + 1 => 5,
+            _ => 1,
+        };
+        last = Some(found);
+        pos = found + 1;
+    }
+    Some(score - text.len() as i64 / 10)
+}
+
+/// 每个关键词都要命中课程名、教师或学期之一
+fn score(course: &Course, query: &str) -> Option<i64> {
+    let mut total = 0;
+    for term in query.split_whitespace() {
+        total += [&course.name, &course.instructors, &course.semester]
+            .iter()
+            .filter_map(|x| fuzzy_score(x, term))
+            .max()?;
+    }
+    Some(total)
+}
+
+fn search<'a>(courses: &'a [Course], query: &str, sort: Sort) -> Vec<&'a Course> {
+    let mut matched = courses
+        .iter()
+        .filter_map(|x| score(x, query).map(|s| (s, x)))
+        .collect::<Vec<_>>();
+    matched.sort_by(|(sa, a), (sb, b)| {
+        let order = match sort {
+            Sort::Semester => b.semester.cmp(&a.semester),
+            Sort::Name => a.name.cmp(&b.name),
+            Sort::Instructor => a.instructors.cmp(&b.instructors),
+        };
+        match query.trim().is_empty() {
+            true => order,
+            false => sb.cmp(sa).then(order),
+        }
+    });
+    matched.into_iter().map(|(_, x)| x).collect()
+}
+
+/// 搜索并多选课程
+pub fn select_courses(cookie: &str) -> Result<Vec<Course>, Error> {
+    let mut courses = load_courses(cookie, false)?;
+    let mut query = String::new();
+    let mut sort = Sort::Semester;
+    loop {
+        let matched = search(&courses, &query, sort);
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("共 {} 门课程", courses.len()))
+            .default(2)
+            .item(format!(
+                "搜索（当前：{}）",
+                match query.is_empty() {
+                    true => "全部",
+                    false => &query,
+                }
+            ))
+            .item(format!("排序（当前：{}）", sort.name()))
+            .item(format!("选择课程（{} 门匹配）", matched.len()))
+            .item("刷新课程列表")
+            .item("返回")
+            .interact()
+            .unwrap_or(4);
+        match selection {
+            0 => {
+                query = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt("搜索课程名、教师或学期（留空显示全部）")
+                    .allow_empty(true)
+                    .interact_text()
+                    .unwrap_or_default();
+            }
+            1 => {
+                let sorts = [Sort::Semester, Sort::Name, Sort::Instructor];
+                let chosen = Select::with_theme(&ColorfulTheme::default())
+                    .with_prompt("排序方式")
+                    .items(&sorts.iter().map(|x| x.name()).collect::<Vec<_>>())
+                    .default(0)
+                    .interact()
+                    .unwrap_or(0);
+                sort = sorts[chosen];
+            }
+            2 => {
+                let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+                    .with_prompt("选择课程（空格选择，回车确认）")
+                    .items(
+                        &matched
+                            .iter()
+                            .map(|x| format!("{} {} {}", x.name, x.instructors, x.semester))
+                            .collect::<Vec<_>>(),
+                    )
+                    .interact()
+                    .unwrap_or_default();
+                if !chosen.is_empty() {
+                    let ret = chosen
+                        .into_iter()
+                        .map(|i| matched[i].clone())
+                        .collect::<Vec<_>>();
+                    debug!("选择了 {} 门课程", ret.len());
+                    return Ok(ret);
+                }
+            }
+            3 => courses = load_courses(cookie, true)?,
+            _ => return Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(name: &str, instructors: &str, semester: &str) -> Course {
+        Course {
+            id: name.to_string(),
+            name: name.to_string(),
+            instructors: instructors.to_string(),
+            semester: semester.to_string(),
+        }
+    }
+
+    #[test]
+    fn subsequence_match() {
+        assert!(fuzzy_score("高等数学A", "高数").is_some());
+        assert!(fuzzy_score("Computer Networks", "cnet").is_some());
+        assert!(fuzzy_score("高等数学", "数高").is_none());
+        assert!(fuzzy_score("线性代数", "概率").is_none());
+        assert_eq!(fuzzy_score("abc", ""), Some(0));
+    }
+
+    #[test]
+    fn prefix_and_consecutive_score_higher() {
+        let prefix = fuzzy_score("数据结构", "数据").unwrap();
+        let middle = fuzzy_score("大数据导论", "数据").unwrap();
+        let scattered = fuzzy_score("数值分析与数据", "数析").unwrap();
+        assert!(prefix > middle);
+        assert!(middle > scattered);
+        // 同样命中时短的文本优先
+        let short = fuzzy_score("物理", "物理").unwrap();
+        let long = fuzzy_score(&format!("物理{}", "实验".repeat(10)), "物理").unwrap();
+        assert!(short > long);
+    }
+
+    #[test]
+    fn every_term_must_match() {
+        let c = course("操作系统", "张三", "2024-2025学年第一学期");
+        assert!(score(&c, "操作 张三").is_some());
+        assert!(score(&c, "操作 2024").is_some());
+        assert!(score(&c, "操作 李四").is_none());
+        assert_eq!(score(&c, "  "), Some(0));
+    }
+
+    #[test]
+    fn search_orders_by_score_then_sort() {
+        let courses = vec![
+            course("大学物理实验", "李四", "2024-2025学年第一学期"),
+            course("物理化学", "王五", "2024-2025学年第二学期"),
+            course("线性代数", "张三", "2023-2024学年第二学期"),
+        ];
+        let names = |x: Vec<&Course>| x.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            names(search(&courses, "物理", Sort::Name)),
+            vec!["物理化学", "大学物理实验"]
+        );
+        assert_eq!(
+            names(search(&courses, "", Sort::Semester)),
+            vec!["物理化学", "大学物理实验", "线性代数"]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use super::download::get_with_cookie;
+use super::main::Error;
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use lazy_static::lazy_static;
+use log::{debug, info, trace, warn};
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::public::hls::{fetch, is_hls, parse_variants};
+use crate::public::storage::sanitize;
+use crate::public::DownloadFile;
+
+const VIDEO_TYPES: [&str; 4] = ["online_video", "lesson_replay", "live_record", "replay"];
+const URL_KEYS: [&str; 4] = ["url", "link", "play_url", "src"];
+const LABEL_KEYS: [&str; 4] = ["resolution", "definition", "quality", "label"];
+/// 没有写分辨率的清晰度名称对应的高度
+const QUALITY_NAMES: [(&str, u32); 6] = [
+    ("原画", 1080),
+    ("蓝光", 1080),
+    ("超清", 720),
+    ("高清", 540),
+    ("标清", 360),
+    ("流畅", 240),
+];
+
+lazy_static! {
+    static ref REGEX_HEIGHT: Regex = Regex::new("(?:\\d+x(\\d+))|(?:(\\d{3,4})\\s*[pP])").unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub label: String,
+    pub url: String,
+    /// m3u8 中的码率，其他来源为 0
+    pub bandwidth: u64,
+}
+
+impl Stream {
+    /// 从 `1920x1080`、`720p` 或 `超清` 这样的名称中得到高度，认不出时为 0
+    fn height(&self) -> u32 {
+        if let Some(c) = REGEX_HEIGHT.captures(&self.label) {
+            return c
+                .get(1)
+                .or(c.get(2))
+                .and_then(|x| x.as_str().parse().ok())
+                .unwrap_or(0);
+        }
+        QUALITY_NAMES
+            .iter()
+            .find(|(name, _)| self.label.contains(name))
+            .map(|(_, height)| *height)
+            .unwrap_or(0)
+    }
+}
+
+/// 按分辨率和码率从高到低排列，认不出的保持接口返回的顺序
+fn sort_streams(streams: &mut [Stream]) {
+    streams.sort_by_key(|x| Reverse((x.height(), x.bandwidth)));
+}
+
+pub fn is_video(element: &Value) -> bool {
+    let kind = element
+        .get("type")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("");
+    VIDEO_TYPES.contains(&kind)
+}
+
+fn is_stream_url(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(u) => [".m3u8", ".mp4", ".flv", ".m4v"]
+            .iter()
+            .any(|x| u.path().ends_with(x)),
+        Err(_) => false,
+    }
+}
+
+/// 活动详情的结构因视频来源不同而不同，递归找出所有像视频地址的字段
+fn collect_streams(value: &Value, label: &str, streams: &mut Vec<Stream>) {
+    match value {
+        Value::Object(map) => {
+            let label = LABEL_KEYS
+                .iter()
+                .find_map(|k| map.get(*k).and_then(|x| x.as_str()))
+                .unwrap_or(label);
+            for key in URL_KEYS {
+                if let Some(url) = map.get(key).and_then(|x| x.as_str()) {
+                    if is_stream_url(url) && !streams.iter().any(|x| x.url == url) {
+                        streams.push(Stream {
+                            label: label.to_string(),
+                            url: url.to_string(),
+                            bandwidth: 0,
+                        });
+                    }
+                }
+            }
+            for v in map.values() {
+                collect_streams(v, label, streams);
+            }
+        }
+        Value::Array(list) => {
+            for v in list {
+                collect_streams(v, label, streams);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 多码率的 m3u8 展开成每个清晰度一项
+fn expand_variants(streams: Vec<Stream>) -> Vec<Stream> {
+    let mut ret = Vec::new();
+    for stream in streams {
+        if !is_hls(&stream.url) {
+            ret.push(stream);
+            continue;
+        }
+        let variants = match fetch(&stream.url) {
+            Ok(data) => parse_variants(&String::from_utf8_lossy(&data), &stream.url),
+            Err(e) => {
+                warn!("获取播放列表失败 {}", e);
+                Vec::new()
+            }
+        };
+        if variants.is_empty() {
+            ret.push(stream);
+            continue;
+        }
+        for variant in variants {
+            ret.push(Stream {
+                label: format!(
+                    "{} {} {}kbps",
+                    stream.label,
+                    variant.resolution,
+                    variant.bandwidth / 1000
+                ),
+                url: variant.url,
+                bandwidth: variant.bandwidth,
+            });
+        }
+    }
+    ret
+}
+
+pub fn get_streams(activity_id: &str, cookie: &str) -> Result<Vec<Stream>, Error> {
+    let resp = get_with_cookie(
+        format!("https://lnt.xmu.edu.cn/api/activities/{}", activity_id),
+        cookie,
+    )?;
+    let json: Value = match resp.json() {
+        Ok(v) => v,
+        Err(_) => return Err(Error::LoginDataInvalid),
+    };
+    trace!("视频活动 json = {}", json);
+    let mut streams = Vec::new();
+    collect_streams(&json, "默认", &mut streams);
+    let mut streams = expand_variants(streams);
+    sort_streams(&mut streams);
+    Ok(streams)
+}
+
+fn extension(url: &str) -> &'static str {
+    if is_hls(url) {
+        return "ts";
+    }
+    match Url::parse(url) {
+        Ok(u) if u.path().ends_with(".flv") => "flv",
+        _ => "mp4",
+    }
+}
+
+/// 视频以任意一种格式下载到 `path` 后的位置
+pub fn local_path(element: &Value, path: &str) -> Option<String> {
+    let title = element
+        .get("title")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("");
+    ["mp4", "flv", "ts"]
+        .iter()
+        .map(|ext| format!("{}/{}.{}", path, sanitize(title), ext))
+        .find(|x| Path::new(x).exists())
+}
+
+pub fn is_downloaded(element: &Value, path: &str) -> bool {
+    local_path(element, path).is_some()
+}
+
+/// 解析视频或回放活动的视频地址并放入下载队列，有多个清晰度时让用户选择，
+/// 返回是否放入了队列
+pub fn queue_video(element: &Value, cookie: &str, path: &str) -> Result<bool, Error> {
+    queue_stream(element, cookie, path, true)
+}
+
+/// 同 `queue_video`，但直接选择最高清晰度
+pub fn queue_best_video(element: &Value, cookie: &str, path: &str) -> Result<bool, Error> {
+    queue_stream(element, cookie, path, false)
+}
+
+fn queue_stream(
+    element: &Value,
+    cookie: &str,
+    path: &str,
+    interactive: bool,
+) -> Result<bool, Error> {
+    let id = element.get("id").unwrap_or(&Value::Null).to_string();
+    let title = element
+        .get("title")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .unwrap_or("");
+    let streams = get_streams(&id, cookie)?;
+    debug!("视频 {} 获取到 streams = {:?}", title, streams);
+    // 已经按清晰度从高到低排好
+    let stream = match streams.len() {
+        0 => {
+            warn!("视频 {} 没有找到可下载的地址", title);
+            return Ok(false);
+        }
+        1 => &streams[0],
+        _ if !interactive => &streams[0],
+        _ => {
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("请选择 {} 的清晰度", title))
+                .default(0)
+                .items(&streams.iter().map(|x| &x.label).collect::<Vec<_>>())
+                .interact()
+                .unwrap_or(0);
+            &streams[selection]
+        }
+    };
+    let file = format!("{}/{}.{}", path, sanitize(title), extension(&stream.url));
+    info!("添加视频 {}", file);
+    DownloadFile::new(&stream.url, &file).run();
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(label: &str, bandwidth: u64) -> Stream {
+        Stream {
+            label: label.to_string(),
+            url: format!("https://example.com/{}.mp4", label),
+            bandwidth,
+        }
+    }
+
+    #[test]
+    fn height_from_label() {
+        assert_eq!(stream("1280x720", 0).height(), 720);
+        assert_eq!(stream("1080P", 0).height(), 1080);
+        assert_eq!(stream("默认 640x360 800kbps", 0).height(), 360);
+        assert_eq!(stream("超清", 0).height(), 720);
+        assert_eq!(stream("默认", 0).height(), 0);
+    }
+
+    #[test]
+    fn best_stream_first() {
+        let mut streams = vec![
+            stream("标清", 0),
+            stream("默认", 0),
+            stream("1080p", 0),
+            stream("默认 1280x720 1000kbps", 1_000_000),
+            stream("默认 1280x720 2000kbps", 2_000_000),
+        ];
+        sort_streams(&mut streams);
+        let labels = streams.iter().map(|x| x.label.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                "1080p",
+                "默认 1280x720 2000kbps",
+                "默认 1280x720 1000kbps",
+                "标清",
+                "默认"
+            ]
+        );
+    }
+}
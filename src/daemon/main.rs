@@ -0,0 +1,217 @@
+use chrono::Local;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+use crate::course_downloader::course::{get_cookie, Course};
+use crate::course_downloader::main::sync_course;
+use crate::course_downloader::search::select_courses;
+use crate::login::main::{relogin, Target};
+use crate::login::profile;
+use crate::login::status::{self, Status};
+use crate::public::download_file;
+use crate::public::logger::Logger;
+use crate::public::notify::{notify, Event};
+use crate::public::storage::{append_text, read_json, write_json};
+use crate::public::thread_manage;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub courses: Vec<Course>,
+    pub interval_minutes: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            courses: Vec::new(),
+            interval_minutes: 120,
+        }
+    }
+}
+
+fn config_path() -> String {
+    format!("{}sync.json", profile::data_path())
+}
+
+fn summary_path() -> String {
+    format!("{}sync.log", profile::data_path())
+}
+
+fn load() -> SyncConfig {
+    read_json(&config_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+fn save(config: &SyncConfig) {
+    let ret = serde_json::to_value(config)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&config_path(), &x));
+    match ret {
+        Ok(_) => info!("已保存同步设置"),
+        Err(e) => warn!("保存同步设置失败 {}", e),
+    }
+}
+
+/// session 失效时用保存的密码重新登录
+fn ensure_session() -> bool {
+    match status::check() {
+        Status::Valid(_) => true,
+        Status::Unknown => {
+            warn!("无法检查登录状态，仍然尝试同步");
+            true
+        }
+        Status::NotLoggedIn | Status::Invalid => {
+            info!("登录已失效，尝试自动重新登录");
+            match relogin(Target::Lnt) {
+                Ok(_) => true,
+                Err(e) => {
+                    e.logger();
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// 把本次同步的结果追加到 sync.log
+fn write_summary(lines: &[String]) {
+    let text = format!("{}\n\n", lines.join("\n"));
+    if let Err(e) = append_text(&summary_path(), &text) {
+        warn!("写入同步记录失败 {}", e);
+    }
+}
+
+/// 同步一次所有选择的课程，等待下载完成后写入记录
+pub fn run_once() {
+    let config = load();
+    let mut summary = vec![format!(
+        "[{}] 同步 {} 门课程",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        config.courses.len()
+    )];
+    if !ensure_session() {
+        notify(Event::SessionExpired {
+            profile: profile::current_name(),
+        });
+        summary.push("登录失效且无法自动登录，跳过本次同步".to_string());
+        write_summary(&summary);
+        return;
+    }
+    let cookie = match get_cookie() {
+        Ok(v) => v,
+        Err(e) => {
+            e.logger();
+            return;
+        }
+    };
+    let mut total = 0;
+    let (_, ids) = download_file::track(|| {
+        for course in &config.courses {
+            match sync_course(&course.id, &cookie) {
+                Ok(files) => {
+                    info!("{} 新增 {} 个文件", course.name, files.len());
+                    summary.push(format!("{}：新增 {} 个", course.name, files.len()));
+                    summary.extend(files.iter().map(|x| format!("  {}", x)));
+                    total += files.len();
+                    if !files.is_empty() {
+                        notify(Event::NewFiles {
+                            course: course.name.clone(),
+                            files,
+                        });
+                    }
+                }
+                Err(e) => {
+                    e.logger();
+                    summary.push(format!("{}：获取失败", course.name));
+                }
+            }
+        }
+    });
+    // 只等待本次放入队列的任务，界面或接口同时下载的不算在内
+    while !download_file::is_finished(&ids) {
+        thread::sleep(Duration::from_secs(1));
+    }
+    let failed = download_file::failed_tasks()
+        .into_iter()
+        .filter(|x| ids.contains(&x.id))
+        .collect::<Vec<_>>();
+    summary.push(format!("共新增 {} 个文件，失败 {} 个", total, failed.len()));
+    summary.extend(failed.iter().map(|x| format!("  失败 {}", x.file)));
+    info!(
+        "同步完成，共新增 {} 个文件，失败 {} 个",
+        total,
+        failed.len()
+    );
+    write_summary(&summary);
+}
+
+/// 按设置的间隔一直同步下去，用于 `--daemon`
+pub fn run_forever() {
+    loop {
+        let config = load();
+        if config.courses.is_empty() {
+            error!("还没有选择要同步的课程，请先在菜单“后台同步”中选择");
+            return;
+        }
+        run_once();
+        info!("{} 分钟后再次同步", config.interval_minutes);
+        thread::sleep(Duration::from_secs(config.interval_minutes.max(1) * 60));
+    }
+}
+
+fn choose_courses() {
+    let courses = match get_cookie().and_then(|x| select_courses(&x)) {
+        Ok(v) => v,
+        Err(e) => {
+            e.logger();
+            return;
+        }
+    };
+    let mut config = load();
+    config.courses = courses;
+    save(&config);
+}
+
+fn set_interval() {
+    let mut config = load();
+    config.interval_minutes = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("同步间隔（分钟）")
+        .default(config.interval_minutes)
+        .interact_text()
+        .unwrap_or(config.interval_minutes);
+    save(&config);
+}
+
+pub fn main() {
+    let config = load();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "后台同步（{} 门课程，每 {} 分钟）",
+            config.courses.len(),
+            config.interval_minutes
+        ))
+        .default(0)
+        .item("选择同步的课程")
+        .item("设置同步间隔")
+        .item("立即同步一次")
+        .item("在后台定时同步")
+        .item("返回")
+        .interact()
+        .unwrap_or(4);
+    match selection {
+        0 => choose_courses(),
+        1 => set_interval(),
+        2 => run_once(),
+        3 => {
+            thread_manage::execute("同步线程", run_forever);
+            info!("已开启后台同步，记录写入 {}", summary_path());
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use chrono::{Duration, NaiveDateTime};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use log::{debug, info, trace, warn};
+use serde_json::Value;
+
+use crate::course_downloader::course::{get_activities, get_cookie, get_courses, Course};
+use crate::course_downloader::download::get_with_cookie;
+use crate::course_downloader::main::Error;
+use crate::public::ical::{countdown, now, parse_rfc3339, write_calendar, Event};
+use crate::public::logger::Logger;
+use crate::public::storage::EXPORT_PATH;
+use crate::public::VOID_VEC;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Homework,
+    Exam,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Homework => "作业",
+            Kind::Exam => "测验",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    pub id: String,
+    pub course: Course,
+    pub title: String,
+    pub kind: Kind,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub submitted: Option<bool>,
+}
+
+impl Deadline {
+    fn from_json(course: &Course, kind: Kind, element: &Value) -> Self {
+        let time = |key: &str| {
+            element
+                .get(key)
+                .unwrap_or(&Value::Null)
+                .as_str()
+                .and_then(parse_rfc3339)
+        };
+        Self {
+            id: element.get("id").unwrap_or(&Value::Null).to_string(),
+            course: course.clone(),
+            title: element
+                .get("title")
+                .unwrap_or(&Value::Null)
+                .as_str()
+                .unwrap_or("")
+                .to_string(),
+            kind,
+            start: time("start_time"),
+            end: time("end_time"),
+            submitted: None,
+        }
+    }
+    pub fn status(&self) -> &'static str {
+        if self.start.map(|t| t > now()).unwrap_or(false) {
+            return "未开放";
+        }
+        match self.submitted {
+            Some(true) => "已提交",
+            Some(false) => "未提交",
+            None => "未知",
+        }
+    }
+}
+
+pub fn main() {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("选择作业功能")
+        .default(0)
+        .item("即将截止的作业和测验")
+        .item("导出到日历")
+        .item("返回")
+        .interact()
+        .unwrap_or(1000);
+    let ret = match selection {
+        0 => show_upcoming(),
+        1 => export_calendar(),
+        _ => Ok(()),
+    };
+    match ret {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+/// 查询提交记录的地址，提交作业后也从这里确认
+pub fn submissions_url(kind: Kind, id: &str) -> String {
+    match kind {
+        Kind::Homework => format!("https://lnt.xmu.edu.cn/api/activities/{}/submissions", id),
+        Kind::Exam => format!("https://lnt.xmu.edu.cn/api/exams/{}/submissions", id),
+    }
+}
+
+/// 查询提交记录，接口没有返回列表时为 None
+pub fn get_submissions(url: &str, cookie: &str) -> Result<Option<Vec<Value>>, Error> {
+    let json: Value = get_with_cookie(url, cookie)?.json().unwrap_or(Value::Null);
+    trace!("提交记录 json = {}", json);
+    let list = json
+        .get("submissions")
+        .or(json.get("list"))
+        .and_then(|x| x.as_array());
+    Ok(list.cloned())
+}
+
+/// 接口返回的列表非空即视为已提交
+pub fn get_submitted(url: &str, cookie: &str) -> Result<Option<bool>, Error> {
+    Ok(get_submissions(url, cookie)?.map(|x| !x.is_empty()))
+}
+
+/// 课程中的作业和测验，不含提交状态
+fn list_deadlines(course: &Course, cookie: &str) -> Result<Vec<Deadline>, Error> {
+    let mut deadlines = get_activities(&course.id, cookie)?
+        .iter()
+        .filter(|x| x.get("type").unwrap_or(&Value::Null).as_str() == Some("homework"))
+        .map(|x| Deadline::from_json(course, Kind::Homework, x))
+        .collect::<Vec<_>>();
+
+    let resp = get_with_cookie(
+        format!("https://lnt.xmu.edu.cn/api/courses/{}/exams", course.id),
+        cookie,
+    )?;
+    let json: Value = resp.json().unwrap_or(Value::Null);
+    trace!("测验 json = {}", json);
+    deadlines.extend(
+        json.get("exams")
+            .unwrap_or(&Value::Null)
+            .as_array()
+            .unwrap_or(&*VOID_VEC)
+            .iter()
+            .map(|x| Deadline::from_json(course, Kind::Exam, x)),
+    );
+    Ok(deadlines)
+}
+
+/// 每个作业和测验都要单独查询一次提交记录
+fn fill_submitted(deadlines: &mut [Deadline], cookie: &str) -> Result<(), Error> {
+    for deadline in deadlines.iter_mut() {
+        let url = submissions_url(deadline.kind, &deadline.id);
+        deadline.submitted = get_submitted(&url, cookie)?;
+    }
+    Ok(())
+}
+
+pub fn get_course_deadlines(course: &Course, cookie: &str) -> Result<Vec<Deadline>, Error> {
+    let mut deadlines = list_deadlines(course, cookie)?;
+    fill_submitted(&mut deadlines, cookie)?;
+    Ok(deadlines)
+}
+
+/// 一门课程中尚未截止的作业和测验，只查询这些的提交状态
+fn course_upcoming(
+    course: &Course,
+    cookie: &str,
+    now: NaiveDateTime,
+) -> Result<Vec<Deadline>, Error> {
+    let mut deadlines = list_deadlines(course, cookie)?;
+    deadlines.retain(|x| x.end.map(|t| t > now).unwrap_or(false));
+    fill_submitted(&mut deadlines, cookie)?;
+    Ok(deadlines)
+}
+
+/// 所有课程中尚未截止的作业和测验，按截止时间排序
+pub fn get_upcoming(cookie: &str) -> Result<Vec<Deadline>, Error> {
+    let now = now();
+    let mut deadlines = Vec::new();
+    for course in get_courses(cookie)? {
+        debug!("获取课程 {} 的作业", course.name);
+        // 一门课程失败时跳过，其他课程的截止时间仍然显示
+        match course_upcoming(&course, cookie, now) {
+            Ok(v) => deadlines.extend(v),
+            Err(e) => {
+                warn!("获取课程 {} 的作业失败，已跳过", course.name);
+                e.logger();
+            }
+        }
+    }
+    deadlines.sort_by_key(|x| x.end);
+    Ok(deadlines)
+}
+
+fn show_upcoming() -> Result<(), Error> {
+    let cookie = get_cookie()?;
+    let deadlines = get_upcoming(&cookie)?;
+    if deadlines.is_empty() {
+        info!("没有即将截止的作业和测验");
+        return Ok(());
+    }
+    for deadline in &deadlines {
+        let end = deadline.end.unwrap_or_default();
+        println!(
+            "[{}] {} {}\t截止 {}\t{}\t{}",
+            deadline.kind.name(),
+            deadline.course.name,
+            deadline.title,
+            end.format("%Y-%m-%d %H:%M"),
+            countdown(&end),
+            deadline.status()
+        );
+    }
+    Ok(())
+}
+
+fn export_calendar() -> Result<(), Error> {
+    let cookie = get_cookie()?;
+    let events = get_upcoming(&cookie)?
+        .iter()
+        .filter_map(|x| {
+            let end = x.end?;
+            Some(Event {
+                uid: format!("lnt-{}-{}", x.kind.name(), x.id),
+                summary: format!("[{}截止] {} {}", x.kind.name(), x.course.name, x.title),
+                location: String::new(),
+                description: format!("提交状态：{}", x.status()),
+                start: end - Duration::minutes(30),
+                end,
+            })
+        })
+        .collect::<Vec<_>>();
+    let path = format!("{}deadlines.ics", EXPORT_PATH);
+    match write_calendar(&path, "作业截止", &events) {
+        Ok(_) => info!("已导出 {} 项截止时间到 {}", events.len(), path),
+        Err(e) => warn!("导出截止时间失败 {}", e),
+    }
+    Ok(())
+}
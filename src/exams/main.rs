@@ -0,0 +1,150 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use lazy_static::lazy_static;
+use log::{debug, info, warn};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::jw::request::{enter_app, get_cookie, get_rows, post_with_cookie, Error};
+use crate::public::ical::{countdown, write_calendar, Event};
+use crate::public::logger::Logger;
+use crate::public::storage::EXPORT_PATH;
+
+const EXAMS_URL: &str = "https://jw.xmu.edu.cn/jwapp/sys/studentWdksapApp/modules/wdksap/wdksap.do";
+
+lazy_static! {
+    static ref REGEX_EXAM_TIME: Regex =
+        Regex::new(r"(\d{4}-\d{2}-\d{2})\s*(\d{1,2}:\d{2})\s*-\s*(\d{1,2}:\d{2})").unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct Exam {
+    pub id: String,
+    pub course: String,
+    pub time: String,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub location: String,
+    pub seat: String,
+}
+
+pub fn main() {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("选择考试功能")
+        .default(0)
+        .item("查看考试安排")
+        .item("导出到日历")
+        .item("返回")
+        .interact()
+        .unwrap_or(1000);
+    let ret = match selection {
+        0 => show_exams(),
+        1 => export_calendar(),
+        _ => Ok(()),
+    };
+    match ret {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+fn get_str(row: &Value, key: &str) -> String {
+    match row.get(key).unwrap_or(&Value::Null) {
+        Value::String(s) => s.to_string(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+/// 解析形如 `2024-01-10 14:30-16:30(星期三)` 的考试时间
+fn parse_time(time: &str) -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+    let captures = match REGEX_EXAM_TIME.captures(time) {
+        Some(c) => c,
+        None => return (None, None),
+    };
+    let parse = |t: &str| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", &captures[1], t), "%Y-%m-%d %H:%M").ok()
+    };
+    (parse(&captures[2]), parse(&captures[3]))
+}
+
+pub fn get_exams() -> Result<Vec<Exam>, Error> {
+    let cookie = get_cookie()?;
+    enter_app("studentWdksapApp", &cookie)?;
+    let json = post_with_cookie(
+        EXAMS_URL,
+        &[("pageSize", "1000"), ("pageNumber", "1")],
+        &cookie,
+    )?;
+    if json.get("datas").is_none() {
+        return Err(Error::LoginDataInvalid);
+    }
+    let mut exams = get_rows(&json, "wdksap")
+        .iter()
+        .map(|row| {
+            let time = get_str(row, "KSSJMS");
+            let (start, end) = parse_time(&time);
+            Exam {
+                id: get_str(row, "WID"),
+                course: get_str(row, "KCM"),
+                time,
+                start,
+                end,
+                location: get_str(row, "JASMC"),
+                seat: get_str(row, "ZWH"),
+            }
+        })
+        .collect::<Vec<_>>();
+    exams.sort_by(|a, b| match (a.start, b.start) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.course.cmp(&b.course),
+    });
+    debug!("获取到 {} 场考试", exams.len());
+    Ok(exams)
+}
+
+fn show_exams() -> Result<(), Error> {
+    let exams = get_exams()?;
+    if exams.is_empty() {
+        info!("暂无考试安排");
+        return Ok(());
+    }
+    for exam in &exams {
+        let countdown = match &exam.start {
+            Some(t) => countdown(t),
+            None => "时间待定".to_string(),
+        };
+        println!(
+            "{}\t{}\t{}\t座位 {}\t{}",
+            exam.course, exam.time, exam.location, exam.seat, countdown
+        );
+    }
+    Ok(())
+}
+
+fn export_calendar() -> Result<(), Error> {
+    let exams = get_exams()?;
+    let events = exams
+        .iter()
+        .filter_map(|exam| {
+            Some(Event {
+                uid: format!("exam-{}", exam.id),
+                summary: format!("{} 考试", exam.course),
+                location: exam.location.clone(),
+                description: format!("座位号 {}", exam.seat),
+                start: exam.start?,
+                end: exam.end?,
+            })
+        })
+        .collect::<Vec<_>>();
+    let path = format!("{}exams.ics", EXPORT_PATH);
+    match write_calendar(&path, "考试安排", &events) {
+        Ok(_) => info!("已导出 {} 场考试到 {}", events.len(), path),
+        Err(e) => warn!("导出考试安排失败 {}", e),
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use super::main::Grade;
+use std::collections::BTreeMap;
+
+/// 厦门大学四分制绩点，返回 None 表示该成绩不计入绩点（如“合格”）
+pub fn grade_point(score: &str) -> Option<f64> {
+    let score = score.trim();
+    if let Ok(v) = score.parse::<f64>() {
+        return Some(match v {
+            v if v >= 90.0 => 4.0,
+            v if v >= 85.0 => 3.7,
+            v if v >= 81.0 => 3.3,
+            v if v >= 78.0 => 3.0,
+            v if v >= 75.0 => 2.7,
+            v if v >= 72.0 => 2.3,
+            v if v >= 68.0 => 2.0,
+            v if v >= 64.0 => 1.7,
+            v if v >= 60.0 => 1.0,
+            _ => 0.0,
+        });
+    }
+    match score {
+        "A" | "优秀" => Some(4.0),
+        "A-" => Some(3.7),
+        "B+" => Some(3.3),
+        "B" | "良好" => Some(3.0),
+        "B-" => Some(2.7),
+        "C+" => Some(2.3),
+        "C" | "中等" => Some(2.0),
+        "C-" => Some(1.7),
+        "D" | "及格" => Some(1.0),
+        "F" | "不及格" => Some(0.0),
+        _ => None,
+    }
+}
+
+/// 按学分加权的平均绩点
+pub fn gpa<'a, I: IntoIterator<Item = &'a Grade>>(grades: I) -> Option<f64> {
+    let mut credits = 0.0;
+    let mut points = 0.0;
+    for grade in grades {
+        if let Some(point) = grade_point(&grade.score) {
+            credits += grade.credit;
+            points += grade.credit * point;
+        }
+    }
+    match credits > 0.0 {
+        true => Some(points / credits),
+        false => None,
+    }
+}
+
+/// 每个学期的绩点，按学期排序
+pub fn term_gpa(grades: &[Grade]) -> BTreeMap<String, Option<f64>> {
+    let mut terms: BTreeMap<String, Vec<&Grade>> = BTreeMap::new();
+    for grade in grades {
+        terms.entry(grade.term.clone()).or_default().push(grade);
+    }
+    terms
+        .into_iter()
+        .map(|(term, grades)| (term, gpa(grades)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grade(term: &str, credit: f64, score: &str) -> Grade {
+        Grade {
+            id: format!("{}-{}", term, score),
+            term: term.to_string(),
+            course: String::new(),
+            course_type: String::new(),
+            credit,
+            score: score.to_string(),
+        }
+    }
+
+    #[test]
+    fn score_to_point() {
+        assert_eq!(grade_point("95"), Some(4.0));
+        assert_eq!(grade_point("90"), Some(4.0));
+        assert_eq!(grade_point("89.5"), Some(3.7));
+        assert_eq!(grade_point(" 81 "), Some(3.3));
+        assert_eq!(grade_point("60"), Some(1.0));
+        assert_eq!(grade_point("59"), Some(0.0));
+        assert_eq!(grade_point("B+"), Some(3.3));
+        assert_eq!(grade_point("良好"), Some(3.0));
+        assert_eq!(grade_point("不及格"), Some(0.0));
+        assert_eq!(grade_point("合格"), None);
+        assert_eq!(grade_point(""), None);
+    }
+
+    #[test]
+    fn weighted_by_credit() {
+        let grades = [
+            grade("a", 3.0, "95"),
+            grade("a", 1.0, "60"),
+            grade("a", 2.0, "合格"),
+        ];
+        let ret = gpa(&grades).unwrap();
+        assert!((ret - 3.25).abs() < 1e-9);
+        assert_eq!(gpa(&[grade("a", 2.0, "通过")]), None);
+        assert_eq!(gpa(&[]), None);
+    }
+
+    #[test]
+    fn per_term() {
+        let grades = [
+            grade("2024-2025-2", 2.0, "85"),
+            grade("2024-2025-1", 1.0, "A"),
+            grade("2024-2025-2", 2.0, "75"),
+        ];
+        let terms = term_gpa(&grades);
+        assert_eq!(
+            terms.keys().collect::<Vec<_>>(),
+            vec!["2024-2025-1", "2024-2025-2"]
+        );
+        assert_eq!(terms["2024-2025-1"], Some(4.0));
+        assert!((terms["2024-2025-2"].unwrap() - 3.2).abs() < 1e-9);
+    }
+}
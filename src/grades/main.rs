@@ -0,0 +1,287 @@
+use super::gpa::{gpa, grade_point, term_gpa};
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use crate::jw::request::{enter_app, get_cookie, get_rows, post_with_cookie, Error};
+use crate::login::profile;
+use crate::public::logger::Logger;
+use crate::public::storage::{read_json, write_csv, write_json, EXPORT_PATH};
+use crate::public::thread_manage;
+
+/// 获取失败后重试的最长间隔（分钟）
+const MAX_RETRY_MINUTES: u64 = 60;
+const GRADES_URL: &str = "https://jw.xmu.edu.cn/jwapp/sys/cjcx/modules/cjcx/xscjcx.do";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grade {
+    pub id: String,
+    pub term: String,
+    pub course: String,
+    pub course_type: String,
+    pub credit: f64,
+    pub score: String,
+}
+
+fn snapshot_path() -> String {
+    format!("{}grades.json", profile::data_path())
+}
+
+pub fn main() {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("选择成绩功能")
+        .default(0)
+        .item("查询成绩")
+        .item("导出 CSV")
+        .item("导出 JSON")
+        .item("新成绩提醒")
+        .item("返回")
+        .interact()
+        .unwrap_or(1000);
+    let ret = match selection {
+        0 => show_grades(),
+        1 => export_csv(),
+        2 => export_json(),
+        3 => watch(),
+        _ => Ok(()),
+    };
+    match ret {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+fn to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+pub fn get_grades() -> Result<Vec<Grade>, Error> {
+    let cookie = get_cookie()?;
+    enter_app("cjcx", &cookie)?;
+    let json = post_with_cookie(
+        GRADES_URL,
+        &[
+            ("querySetting", "[]"),
+            ("pageSize", "1000"),
+            ("pageNumber", "1"),
+        ],
+        &cookie,
+    )?;
+    let rows = get_rows(&json, "xscjcx");
+    if rows.is_empty() && json.get("datas").is_none() {
+        return Err(Error::LoginDataInvalid);
+    }
+    let mut grades = rows
+        .iter()
+        .map(|row| Grade {
+            id: to_string(row.get("WID").unwrap_or(&Value::Null)),
+            term: to_string(
+                row.get("XNXQDM_DISPLAY")
+                    .or(row.get("XNXQDM"))
+                    .unwrap_or(&Value::Null),
+            ),
+            course: to_string(row.get("KCM").unwrap_or(&Value::Null)),
+            course_type: to_string(row.get("KCXZDM_DISPLAY").unwrap_or(&Value::Null)),
+            credit: to_string(row.get("XF").unwrap_or(&Value::Null))
+                .parse()
+                .unwrap_or(0.0),
+            score: to_string(row.get("ZCJ").unwrap_or(&Value::Null)),
+        })
+        .collect::<Vec<_>>();
+    grades.sort_by(|a, b| a.term.cmp(&b.term).then(a.course.cmp(&b.course)));
+    debug!("获取到 {} 条成绩", grades.len());
+    Ok(grades)
+}
+
+fn load_snapshot() -> Vec<Grade> {
+    read_json(&snapshot_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+fn save_snapshot(grades: &[Grade]) {
+    let ret = serde_json::to_value(grades)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&snapshot_path(), &x));
+    if let Err(e) = ret {
+        warn!("保存成绩快照失败 {}", e);
+    }
+}
+
+fn format_gpa(gpa: Option<f64>) -> String {
+    match gpa {
+        Some(v) => format!("{:.2}", v),
+        None => "-".to_string(),
+    }
+}
+
+fn show_grades() -> Result<(), Error> {
+    let grades = get_grades()?;
+    let mut term = "";
+    for grade in &grades {
+        if grade.term != term {
+            term = &grade.term;
+            println!("\n{}", term);
+            println!(
+                "{:<30}\t{:<8}\t{:<6}\t{:<6}\t绩点",
+                "课程", "性质", "学分", "成绩"
+            );
+        }
+        println!(
+            "{:<30}\t{:<8}\t{:<6}\t{:<6}\t{}",
+            grade.course,
+            grade.course_type,
+            grade.credit,
+            grade.score,
+            grade_point(&grade.score)
+                .map(|x| format!("{:.1}", x))
+                .unwrap_or("-".to_string())
+        );
+    }
+    println!();
+    for (term, gpa) in term_gpa(&grades) {
+        println!("{} 学期绩点 {}", term, format_gpa(gpa));
+    }
+    println!("累计绩点 {}", format_gpa(gpa(&grades)));
+    save_snapshot(&grades);
+    Ok(())
+}
+
+fn export_csv() -> Result<(), Error> {
+    let grades = get_grades()?;
+    let path = format!("{}grades.csv", EXPORT_PATH);
+    let rows = grades
+        .iter()
+        .map(|x| {
+            vec![
+                x.term.clone(),
+                x.course.clone(),
+                x.course_type.clone(),
+                x.credit.to_string(),
+                x.score.clone(),
+                grade_point(&x.score)
+                    .map(|x| x.to_string())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    match write_csv(
+        &path,
+        &["学期", "课程", "性质", "学分", "成绩", "绩点"],
+        &rows,
+    ) {
+        Ok(_) => info!("成绩已导出到 {}", path),
+        Err(e) => warn!("导出成绩失败 {}", e),
+    }
+    Ok(())
+}
+
+fn export_json() -> Result<(), Error> {
+    let grades = get_grades()?;
+    let path = format!("{}grades.json", EXPORT_PATH);
+    let terms = term_gpa(&grades)
+        .into_iter()
+        .map(|(term, gpa)| serde_json::json!({"term": term, "gpa": gpa}))
+        .collect::<Vec<_>>();
+    let json = serde_json::json!({
+        "grades": grades,
+        "terms": terms,
+        "gpa": gpa(&grades),
+    });
+    match write_json(&path, &json) {
+        Ok(_) => info!("成绩已导出到 {}", path),
+        Err(e) => warn!("导出成绩失败 {}", e),
+    }
+    Ok(())
+}
+
+/// 与上一次保存的快照比较，返回新出的成绩
+fn diff_snapshot(grades: &[Grade]) -> Vec<Grade> {
+    let known = load_snapshot()
+        .into_iter()
+        .map(|x| (x.id, x.score))
+        .collect::<HashSet<_>>();
+    grades
+        .iter()
+        .filter(|x| !known.contains(&(x.id.clone(), x.score.clone())))
+        .cloned()
+        .collect()
+}
+
+fn watch() -> Result<(), Error> {
+    get_cookie()?;
+    let minutes: u64 = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("检查间隔（分钟）")
+        .default(30)
+        .interact_text()
+        .unwrap_or(30);
+    if load_snapshot().is_empty() {
+        save_snapshot(&get_grades()?);
+    }
+    thread_manage::execute("成绩提醒线程", move || {
+        let mut failures = 0;
+        loop {
+            let wait = match failures {
+                0 => minutes.max(1),
+                n => retry_minutes(n),
+            };
+            thread::sleep(Duration::from_secs(wait * 60));
+            failures = match check_new_grades() {
+                Ok(_) => 0,
+                Err(e) => {
+                    e.logger();
+                    warn!(
+                        "获取成绩失败（连续 {} 次），{} 分钟后重试",
+                        failures + 1,
+                        retry_minutes(failures + 1)
+                    );
+                    failures + 1
+                }
+            };
+        }
+    });
+    info!("已开启新成绩提醒，每 {} 分钟检查一次", minutes);
+    Ok(())
+}
+
+/// 第 n 次失败后等待 1、2、4…分钟再重试，最长 `MAX_RETRY_MINUTES`
+fn retry_minutes(failures: u32) -> u64 {
+    (1u64 << failures.saturating_sub(1).min(16)).min(MAX_RETRY_MINUTES)
+}
+
+fn check_new_grades() -> Result<(), Error> {
+    let grades = get_grades()?;
+    for grade in diff_snapshot(&grades) {
+        print!("\x07");
+        warn!(
+            "新成绩：{} {} {} 学分 {}",
+            grade.term, grade.course, grade.credit, grade.score
+        );
+    }
+    save_snapshot(&grades);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_backoff() {
+        assert_eq!(retry_minutes(1), 1);
+        assert_eq!(retry_minutes(2), 2);
+        assert_eq!(retry_minutes(4), 8);
+        assert_eq!(retry_minutes(7), MAX_RETRY_MINUTES);
+        assert_eq!(retry_minutes(100), MAX_RETRY_MINUTES);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod gpa;
+pub mod main;
+pub use main::main;
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use log::{trace, LevelFilter};
+use reqwest::header::COOKIE;
+use serde_json::Value;
+
+use crate::login::main::get_jw_session;
+use crate::public::http::{client, send};
+use crate::public::logger::{Logger, LoggerData};
+
+#[derive(Debug)]
+pub enum Error {
+    LoginDataInvalid,
+    NetworkFailure,
+}
+
+impl Logger for Error {
+    fn get_logger(&self) -> LoggerData {
+        match *self {
+            Error::LoginDataInvalid => {
+                LoggerData::new(LevelFilter::Warn, "教务系统账号已失效，请重新登录。")
+            }
+            Error::NetworkFailure => LoggerData::new(LevelFilter::Error, "网络不通，请检查网络。"),
+        }
+    }
+}
+
+pub fn get_cookie() -> Result<String, Error> {
+    get_jw_session().ok_or(Error::LoginDataInvalid)
+}
+
+/// 进入教务系统的某个应用，金智教务需要先访问应用首页才能调用接口
+pub fn enter_app(app: &str, cookie: &str) -> Result<(), Error> {
+    let url = format!("https://jw.xmu.edu.cn/jwapp/sys/{}/*default/index.do", app);
+    match send(client().get(url).header(COOKIE, cookie)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Error::NetworkFailure),
+    }
+}
+
+pub fn post_with_cookie(url: &str, form: &[(&str, &str)], cookie: &str) -> Result<Value, Error> {
+    let resp = match send(client().post(url).header(COOKIE, cookie).form(form)) {
+        Ok(e) => e,
+        Err(_) => return Err(Error::NetworkFailure),
+    };
+    let json: Value = match resp.json() {
+        Ok(v) => v,
+        Err(_) => return Err(Error::LoginDataInvalid),
+    };
+    trace!("教务接口 {} 返回 json = {}", url, json);
+    Ok(json)
+}
+
+/// 取出金智教务接口返回的 `datas.<name>.rows`
+pub fn get_rows(json: &Value, name: &str) -> Vec<Value> {
+    json.get("datas")
+        .unwrap_or(&Value::Null)
+        .get(name)
+        .unwrap_or(&Value::Null)
+        .get("rows")
+        .unwrap_or(&Value::Null)
+        .as_array()
+        .map(|x| x.to_owned())
+        .unwrap_or_default()
+}
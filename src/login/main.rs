@@ -1,14 +1,21 @@
 use crate::public::logger::{Logger, LoggerData};
 
+use super::profile;
 use super::qrcode::{State, UrlConsoleQRCode};
 use super::session::SessionClient;
+use crate::public::http::Response;
+use crate::public::ical::{beijing, now};
+use crate::public::webvpn;
+use crate::setting::config;
 use base64::Engine;
 use crossterm::cursor::{MoveRight, MoveUp};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::Select;
+use dialoguer::{Confirm, Select};
 use lazy_static::lazy_static;
-use log::{info, trace, LevelFilter};
+use log::{debug, info, trace, warn, LevelFilter};
 use rand::seq::IndexedRandom;
 use regex::Regex;
 use serde_json::Value;
@@ -16,14 +23,12 @@ use soft_aes::aes::aes_enc_cbc;
 use std::collections::HashMap;
 use std::io::{stdin, stdout, Write};
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const AES_CHARS: &[u8] = b"ABCDEFGHJKMNPQRSTWXYZabcdefhijkmnprstwxyz2345678";
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
-lazy_static! {
-    static ref SESSION: Mutex<Option<String>> = Mutex::new(None);
-}
 lazy_static! {
     static ref REGEX_EXECUTION: Arc<Regex> = Arc::new(
         Regex::new("<input[^>]*?name=\"execution\"[^>]*?value=\"([^\"]*)\"[^>]*?>").unwrap()
@@ -67,6 +72,9 @@ pub enum Error {
     Account,
     Input,
     Encrypt,
+    Timeout,
+    Cancel,
+    NoCredentials,
 }
 
 impl Logger for Error {
@@ -82,10 +90,41 @@ impl Logger for Error {
             Error::Account => LoggerData::new(LevelFilter::Warn, "账号可能被风控，请使用扫码登录"),
             Error::Input => LoggerData::new(LevelFilter::Error, "输入异常"),
             Error::Encrypt => LoggerData::new(LevelFilter::Error, "密码加密失败"),
+            Error::Timeout => LoggerData::new(LevelFilter::Warn, "扫码登录超时，请重试"),
+            Error::Cancel => LoggerData::new(LevelFilter::Info, "已取消登录"),
+            Error::NoCredentials => {
+                LoggerData::new(LevelFilter::Warn, "当前账号没有保存密码，无法自动登录")
+            }
         }
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Lnt,
+    Jw,
+    WebVpn,
+}
+
+impl Target {
+    pub fn url(&self) -> &'static str {
+        match *self {
+            Target::Lnt => "https://lnt.xmu.edu.cn/",
+            Target::Jw => {
+                "https://jw.xmu.edu.cn/login?service=https://jw.xmu.edu.cn/new/index.html"
+            }
+            Target::WebVpn => webvpn::LOGIN_URL,
+        }
+    }
+}
+
+/// 登录 WebVPN 门户本身时不能再经过旧的门户凭据
+fn start_login(target: Target) {
+    if let Target::WebVpn = target {
+        profile::update(|x| x.webvpn_cookie = None);
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
         if e.is_request() {
@@ -96,6 +135,12 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(_: serde_json::Error) -> Self {
+        Error::ContentGet
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(_: std::io::Error) -> Self {
         Error::Input
@@ -121,35 +166,98 @@ pub fn main() {
         .default(0)
         .item("课程中心 https://lnt.xmu.edu.cn/")
         .item("教务系统 https://jw.xmu.edu.cn/")
+        .item("校外访问 https://webvpn.xmu.edu.cn/")
         .interact()
         .unwrap_or(3);
-    let target_url = match target {
-        0 => "https://lnt.xmu.edu.cn/",
-        1 => panic!("此功能未实现"), //"https://jw.xmu.edu.cn/login?service=https://jw.xmu.edu.cn/new/index.html",
-        _ => "",
+    let target = match target {
+        0 => Target::Lnt,
+        1 => Target::Jw,
+        2 => Target::WebVpn,
+        _ => return,
     };
     let ret = match by {
-        0 => qr_login(target_url),
-        1 => password_login(target_url),
+        0 => qr_login(target, &|x| x.show(), &wait_key),
+        1 => password_login(target),
         _ => Ok(()),
     };
     match ret {
         Ok(_) => {}
         Err(e) => e.logger(),
     }
-    info!("获取到session = {:?}", get_session());
+    match target {
+        Target::Lnt => info!("获取到session = {:?}", get_session()),
+        Target::Jw => info!("获取到jw session = {:?}", get_jw_session()),
+        Target::WebVpn => info!("获取到 WebVPN cookie = {:?}", webvpn::cookie()),
+    }
 }
 
-fn password_login(target: &str) -> Result<(), Error> {
+fn password_login(target: Target) -> Result<(), Error> {
+    start_login(target);
     let mut session = SessionClient::new();
 
+    let saved = profile::current();
+    let use_saved = match &saved.username {
+        Some(username) => {
+            Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("账号")
+                .default(0)
+                .item(format!("使用保存的账号 {}", username))
+                .item("输入其他账号")
+                .interact()
+                .unwrap_or(1)
+                == 0
+        }
+        _ => false,
+    };
+
     let mut username = String::with_capacity(30);
-    print!("请输入学号：");
-    stdout().flush()?;
-    stdin().read_line(&mut username)?;
+    match use_saved {
+        true => username = saved.username.clone().unwrap_or_default(),
+        false => {
+            print!("请输入学号：");
+            stdout().flush()?;
+            stdin().read_line(&mut username)?;
+        }
+    }
     let username = username.trim();
     trace!("获取到 username = {:?}", username);
 
+    check_captcha(&mut session, username)?;
+
+    let password = match (use_saved, &saved.password) {
+        (true, Some(password)) => password.clone(),
+        _ => read_password()?,
+    };
+    let password = password.as_str();
+
+    submit_password(&mut session, target, username, password)?;
+    if !use_saved || saved.password.is_none() {
+        remember_credentials(username, password);
+    }
+    Ok(())
+}
+
+/// 用当前账号保存的学号和密码重新登录，不需要交互
+pub fn relogin(target: Target) -> Result<(), Error> {
+    let saved = profile::current();
+    let (username, password) = match (saved.username, saved.password) {
+        (Some(username), Some(password)) => (username, password),
+        _ => return Err(Error::NoCredentials),
+    };
+    // 门户凭据可能也已经过期，先重新登录门户
+    if config::get().webvpn && !matches!(target, Target::WebVpn) {
+        relogin(Target::WebVpn)?;
+    }
+    start_login(target);
+    let mut session = SessionClient::new();
+    check_captcha(&mut session, &username)?;
+    submit_password(&mut session, target, &username, &password)?;
+    info!("已自动重新登录账号 {}", profile::current_name());
+    Ok(())
+}
+
+/// 需要验证码时说明账号被风控，只能扫码登录
+fn check_captcha(session: &mut SessionClient, username: &str) -> Result<(), Error> {
     let response = session.get(format!(
         "https://ids.xmu.edu.cn/authserver/checkNeedCaptcha.htl?username={}_={}",
         username,
@@ -164,30 +272,22 @@ fn password_login(target: &str) -> Result<(), Error> {
     if state {
         return Err(Error::Account);
     }
+    Ok(())
+}
 
-    let mut password = String::with_capacity(100);
-    print!("请输入密码：");
-    stdout().flush()?;
-    stdin().read_line(&mut password)?;
-    let password = password.trim();
-    trace!("获取到 password = {:?}", password);
-
-    if crate::public::logger::LEVEL == log::LevelFilter::Trace {
-        execute! {stdout(),MoveUp(1)}?;
-    }
-    execute! {stdout(),MoveUp(1),MoveRight(12)}?;
-    for _ in 0..password.len() {
-        print!("*");
-    }
-    println!();
-
-    let service = get_service(&mut session, target)?;
+fn submit_password(
+    session: &mut SessionClient,
+    target: Target,
+    username: &str,
+    password: &str,
+) -> Result<(), Error> {
+    let service = get_service(session, target.url())?;
 
     let response = session.get(format!(
         "https://ids.xmu.edu.cn/authserver/login?type=userNameLogin&service={}",
         service
     ))?;
-    let text = response.text()?;
+    let text = response.text();
 
     let execution = get_execution(&text)?;
     let salt = get_salt(&text)?;
@@ -217,44 +317,136 @@ fn password_login(target: &str) -> Result<(), Error> {
         &data,
     )?;
 
-    for e in response.cookies() {
-        info!("获取到 cookie {}={}", e.name(), e.value());
-        match e.name() {
-            "session" => {
-                let mut lock = SESSION.lock().unwrap();
-                *lock = Some(e.value().to_string());
-                return Ok(());
+    save_session(session, target, response)
+}
+
+/// 登录成功后询问是否把账号密码保存到当前账号
+fn remember_credentials(username: &str, password: &str) {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("保存到账号 {}", profile::current_name()))
+        .default(0)
+        .item("只保存学号")
+        .item("保存学号和密码（明文保存在本地）")
+        .item("不保存")
+        .interact()
+        .unwrap_or(2);
+    let (username, password) = (username.to_string(), password.to_string());
+    match selection {
+        0 => profile::update(|x| x.username = Some(username)),
+        1 if !confirm_save_password() => profile::update(|x| x.username = Some(username)),
+        1 => profile::update(|x| {
+            x.username = Some(username);
+            x.password = Some(password);
+        }),
+        _ => {}
+    }
+}
+
+/// 密码没有加密，保存前说明风险，默认不保存
+fn confirm_save_password() -> bool {
+    warn!("密码会以明文保存在 data/profiles.json 中，能读取这个文件的人都能看到");
+    warn!("只在后台同步需要自动重新登录时保存，可以在“切换账号”中删除保存的密码");
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("仍然保存密码？")
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+/// 等待一段时间，期间按 q 或 Esc 返回 true 表示取消
+pub fn wait_key(duration: Duration) -> bool {
+    if enable_raw_mode().is_err() {
+        thread::sleep(duration);
+        return false;
+    }
+    let deadline = Instant::now() + duration;
+    let mut cancel = false;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match event::poll(left) {
+            Ok(true) => {
+                if let Ok(Event::Key(key)) = event::read() {
+                    if key.kind == KeyEventKind::Press
+                        && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    {
+                        cancel = true;
+                        break;
+                    }
+                }
             }
-            "asessionid" => {}
-            _ => {}
+            _ => break,
         }
     }
-
-    Err(Error::Account)
+    disable_raw_mode().unwrap_or_default();
+    cancel
 }
 
-fn qr_login(target: &str) -> Result<(), Error> {
+/// `show` 负责把二维码展示给用户，命令行下按设置直接输出到终端；
+/// `wait` 在两次查询之间等待，返回 true 时取消登录
+pub fn qr_login(
+    target: Target,
+    show: &dyn Fn(&mut UrlConsoleQRCode) -> Result<(), Error>,
+    wait: &dyn Fn(Duration) -> bool,
+) -> Result<(), Error> {
+    let config = config::get();
+    start_login(target);
     let mut session = SessionClient::new();
-    let service = get_service(&mut session, target)?;
+    let service = get_service(&mut session, target.url())?;
     let login_page = session.get(format!(
         "https://ids.xmu.edu.cn/authserver/login?type=qrLogin&service={}",
         service
     ))?;
-    let login_text = login_page.text()?;
+    let login_text = login_page.text();
     let execution = get_execution(&login_text)?;
     let mut qrcode = UrlConsoleQRCode::new(&get_qrcode_id(&mut session)?);
-    qrcode.show()?;
+    show(&mut qrcode)?;
     trace!("二维码的data = {:?}", qrcode.get_data());
+
+    let base_interval = Duration::from_millis(config.qrcode_poll_interval_ms.max(200));
+    let timeout = Duration::from_secs(config.qrcode_timeout_secs);
+    let start = Instant::now();
+    let mut interval = base_interval;
+    let mut renewals = 0;
+    let mut last_state = None;
     loop {
-        match qrcode.get_state()? {
-            Some(State::Waiting) => trace!("等待扫描二维码"),
-            Some(State::Scanned) => trace!("扫描成功，等待确认"),
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout);
+        }
+        if wait(interval) {
+            return Err(Error::Cancel);
+        }
+        let state = match qrcode.get_state() {
+            Ok(v) => v,
+            Err(Error::Service) => None,
+            Err(e) => return Err(e),
+        };
+        if state.is_some() && state != last_state {
+            match state {
+                Some(State::Waiting) => info!("等待扫描二维码，按 q 或 Esc 取消"),
+                Some(State::Scanned) => info!("已扫描，等待确认"),
+                _ => {}
+            }
+            last_state = state;
+        }
+        match state {
+            Some(State::Waiting) | Some(State::Scanned) => interval = base_interval,
             Some(State::Success) => break,
             Some(State::Outdated) => {
+                if renewals >= config.qrcode_max_renew {
+                    return Err(Error::Timeout);
+                }
+                renewals += 1;
+                info!(
+                    "二维码已过期，自动刷新（{}/{}）",
+                    renewals, config.qrcode_max_renew
+                );
                 qrcode.renew(&get_qrcode_id(&mut session)?);
-                qrcode.show()?;
+                show(&mut qrcode)?;
+                last_state = None;
+            }
+            None => {
+                interval = (interval * 2).min(MAX_POLL_INTERVAL);
+                debug!("请求太频繁，{} 毫秒后重试", interval.as_millis());
             }
-            None => trace!("请求太频繁"),
         }
     }
     let data = get_qrcode_data(qrcode.get_id(), execution);
@@ -265,22 +457,65 @@ fn qr_login(target: &str) -> Result<(), Error> {
         ),
         &data,
     )?;
-    for e in response.cookies() {
-        info!("获取到 cookie {}={}", e.name(), e.value());
-        match e.name() {
-            "session" => {
-                let mut lock = SESSION.lock().unwrap();
-                *lock = Some(e.value().to_string());
+    save_session(&session, target, response)
+}
+
+fn save_session(session: &SessionClient, target: Target, response: Response) -> Result<(), Error> {
+    match target {
+        Target::Lnt => {
+            trace!("登录后跳转到 {}", response.url());
+            if let Some((value, expires)) = session.get_cookie(Target::Lnt.url(), "session") {
+                info!("获取到 cookie session={}", value);
+                let expires = expires.map(|t| t.with_timezone(&beijing()).naive_local());
+                profile::update(|x| {
+                    x.session = Some(value);
+                    x.session_saved_at = Some(now());
+                    x.session_expires = expires;
+                });
+                return Ok(());
+            }
+        }
+        Target::Jw => {
+            trace!("登录后跳转到 {}", response.url());
+            if let Some(cookie) = session.get_cookie_header("https://jw.xmu.edu.cn/") {
+                info!("获取到 jw cookie {}", cookie);
+                profile::update(|x| x.jw_session = Some(cookie));
+                return Ok(());
+            }
+        }
+        Target::WebVpn => {
+            trace!("登录后跳转到 {}", response.url());
+            let url = format!("https://{}/", webvpn::HOST);
+            if let Some(cookie) = session.get_cookie_header(&url) {
+                info!("获取到 WebVPN cookie {}", cookie);
+                profile::update(|x| x.webvpn_cookie = Some(cookie));
                 return Ok(());
             }
-            "asessionid" => {}
-            _ => {}
         }
     }
 
     Err(Error::Account)
 }
 
+fn read_password() -> Result<String, Error> {
+    let mut password = String::with_capacity(100);
+    print!("请输入密码：");
+    stdout().flush()?;
+    stdin().read_line(&mut password)?;
+    let password = password.trim().to_string();
+    trace!("获取到 password = {:?}", password);
+
+    if crate::public::logger::LEVEL == log::LevelFilter::Trace {
+        execute! {stdout(),MoveUp(1)}?;
+    }
+    execute! {stdout(),MoveUp(1),MoveRight(12)}?;
+    for _ in 0..password.len() {
+        print!("*");
+    }
+    println!();
+    Ok(password)
+}
+
 fn random_string(len: usize) -> String {
     let mut rng = rand::rng();
     let mut result = String::new();
@@ -291,7 +526,11 @@ fn random_string(len: usize) -> String {
 }
 
 pub fn get_session() -> Option<String> {
-    SESSION.lock().unwrap().as_ref().map(|x| x.clone())
+    profile::current().session
+}
+
+pub fn get_jw_session() -> Option<String> {
+    profile::current().jw_session
 }
 
 fn get_qrcode_data(qrcode_id: &str, execution: &str) -> HashMap<String, String> {
@@ -317,7 +556,7 @@ fn get_qrcode_id(session: &mut SessionClient) -> Result<String, Error> {
             "https://ids.xmu.edu.cn/authserver/qrCode/getToken?ts={}",
             get_timestamp()
         ))?
-        .text()?)
+        .text())
 }
 
 pub fn get_timestamp() -> u128 {
@@ -1,4 +1,6 @@
 pub mod main;
+pub mod profile;
 pub mod qrcode;
 pub mod session;
+pub mod status;
 pub use main::main;
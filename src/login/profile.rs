@@ -0,0 +1,257 @@
+use chrono::NaiveDateTime;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::public::storage::{read_json, sanitize, write_json, DATA_PATH};
+
+pub const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_DOWNLOAD_PATH: &str = "./download/";
+/// 以前所有账号共用、现在按账号分开保存的文件
+const PROFILE_FILES: &[&str] = &[
+    "courses.json",
+    "filters.json",
+    "grades.json",
+    "announcements.json",
+    "sync.json",
+    "sync.log",
+];
+
+lazy_static! {
+    static ref PROFILES: Mutex<Profiles> = Mutex::new(load());
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub session: Option<String>,
+    /// 课程中心 session 的获取时间和过期时间（北京时间）
+    pub session_saved_at: Option<NaiveDateTime>,
+    pub session_expires: Option<NaiveDateTime>,
+    pub jw_session: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub download_path: String,
+    /// WebVPN 门户的 cookie，开启 WebVPN 时随请求发送
+    pub webvpn_cookie: Option<String>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            session: None,
+            session_saved_at: None,
+            session_expires: None,
+            jw_session: None,
+            username: None,
+            password: None,
+            download_path: DEFAULT_DOWNLOAD_PATH.to_string(),
+            webvpn_cookie: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Profiles {
+    current: String,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    fn current_mut(&mut self) -> &mut Profile {
+        if self.current.is_empty() {
+            self.current = DEFAULT_PROFILE.to_string();
+        }
+        self.profiles.entry(self.current.clone()).or_default()
+    }
+}
+
+fn profiles_path() -> String {
+    format!("{}profiles.json", DATA_PATH)
+}
+
+fn load() -> Profiles {
+    let profiles: Profiles = read_json(&profiles_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default();
+    migrate(&profile_path(&profiles.current));
+    profiles
+}
+
+/// 把以前放在 data 下的共用文件移动到当前账号的目录
+fn migrate(dir: &str) {
+    for name in PROFILE_FILES {
+        let old = format!("{}{}", DATA_PATH, name);
+        let new = format!("{}{}", dir, name);
+        if !Path::new(&old).exists() || Path::new(&new).exists() {
+            continue;
+        }
+        let ret = fs::create_dir_all(dir).and_then(|_| fs::rename(&old, &new));
+        match ret {
+            Ok(_) => info!("已移动 {} 到 {}", old, new),
+            Err(e) => warn!("移动 {} 失败 {}", old, e),
+        }
+    }
+}
+
+fn save(profiles: &Profiles) {
+    let ret = serde_json::to_value(profiles)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&profiles_path(), &x));
+    if let Err(e) = ret {
+        warn!("保存账号信息失败 {}", e);
+    }
+    // 可能保存了密码，只允许自己读写
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(profiles_path(), fs::Permissions::from_mode(0o600)).unwrap_or_default();
+    }
+}
+
+fn profile_path(name: &str) -> String {
+    let name = match sanitize(name) {
+        v if v.is_empty() => DEFAULT_PROFILE.to_string(),
+        // 避免 `..` 一类的名字指向 data 以外
+        v if v.starts_with('.') => format!("_{}", v),
+        v => v,
+    };
+    format!("{}{}/", DATA_PATH, name)
+}
+
+/// 当前账号的数据目录，课程缓存、过滤条件、成绩快照、公告已读记录和同步设置按账号分开保存
+pub fn data_path() -> String {
+    profile_path(&current_name())
+}
+
+pub fn current_name() -> String {
+    match PROFILES.lock().unwrap().current.as_str() {
+        "" => DEFAULT_PROFILE.to_string(),
+        name => name.to_string(),
+    }
+}
+
+pub fn current() -> Profile {
+    PROFILES.lock().unwrap().current_mut().clone()
+}
+
+/// 修改当前账号并写回文件
+pub fn update<F: FnOnce(&mut Profile)>(f: F) {
+    let mut lock = PROFILES.lock().unwrap();
+    f(lock.current_mut());
+    save(&lock);
+}
+
+/// 切换到指定账号，不存在时新建
+pub fn switch(name: &str) {
+    let mut lock = PROFILES.lock().unwrap();
+    lock.current = name.to_string();
+    lock.current_mut();
+    save(&lock);
+    info!("当前账号：{}", name);
+}
+
+fn names() -> Vec<String> {
+    let mut lock = PROFILES.lock().unwrap();
+    lock.current_mut();
+    lock.profiles.keys().cloned().collect()
+}
+
+fn remove(name: &str) {
+    let mut lock = PROFILES.lock().unwrap();
+    lock.profiles.remove(name);
+    if lock.current == name {
+        lock.current = DEFAULT_PROFILE.to_string();
+    }
+    save(&lock);
+}
+
+/// 从命令行参数中读取 `--profile <名称>` 或 `--profile=<名称>`
+pub fn from_args() -> Option<String> {
+    let mut args = std::env::args();
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+pub fn menu_label() -> String {
+    format!("切换账号（当前：{}）", current_name())
+}
+
+fn input(prompt: &str, default: String) -> String {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact_text()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+pub fn main() {
+    let names = names();
+    let current_name = current_name();
+    let mut items = names
+        .iter()
+        .map(|x| match *x == current_name {
+            true => format!("{}（当前）", x),
+            false => x.clone(),
+        })
+        .collect::<Vec<_>>();
+    items.push("新建账号".to_string());
+    items.push("修改当前账号的下载目录".to_string());
+    items.push("删除当前账号保存的密码".to_string());
+    items.push("删除账号".to_string());
+    items.push("返回".to_string());
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("切换账号")
+        .items(&items)
+        .default(names.iter().position(|x| *x == current_name).unwrap_or(0))
+        .interact()
+        .unwrap_or(usize::MAX);
+    match selection.checked_sub(names.len()) {
+        None => switch(&names[selection]),
+        Some(0) => {
+            let name = input("账号名称", String::new());
+            if name.is_empty() {
+                return;
+            }
+            switch(&name);
+        }
+        Some(1) => {
+            let path = input("下载目录", current().download_path);
+            if !path.is_empty() {
+                update(|x| x.download_path = path);
+            }
+        }
+        Some(2) => {
+            update(|x| x.password = None);
+            info!("已删除账号 {} 保存的密码", current_name);
+        }
+        Some(3) => {
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("删除哪个账号")
+                .items(&names)
+                .interact()
+                .unwrap_or(usize::MAX);
+            if let Some(name) = names.get(selection) {
+                remove(name);
+                info!("已删除账号 {}", name);
+            }
+        }
+        _ => {}
+    }
+}
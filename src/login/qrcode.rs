@@ -1,15 +1,23 @@
-use std::fs::remove_file;
+use std::env::{temp_dir, var};
+use std::fs::{remove_file, File};
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::process::Command;
 
 use super::main::{get_timestamp, Error};
-use crate::public::download_file::download_file;
-use crate::public::DownloadFile;
+use crate::public::http::{client, send};
+use crate::setting::config::{self, QrMode};
 use anyhow::Result;
 use bardecoder;
-use qrcode::QrCode;
-use reqwest::blocking::Client;
+use base64::Engine;
+use log::{debug, warn};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{Color, QrCode};
 
-const QRCODE_PATH: &str = "qrcode.png";
+/// sixel 输出时每个模块占用的像素
+const SIXEL_SCALE: usize = 4;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum State {
     Waiting,
     Success,
@@ -20,6 +28,30 @@ pub enum State {
 pub struct UrlConsoleQRCode {
     qrcode_id: String,
     data: Option<String>,
+    image: Vec<u8>,
+    viewer_file: Option<PathBuf>,
+}
+
+/// 当前终端支持的图片协议
+enum Protocol {
+    Kitty,
+    ITerm,
+    Sixel,
+}
+
+fn detect_protocol() -> Option<Protocol> {
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    if var("KITTY_WINDOW_ID").is_ok() || term.contains("kitty") || program == "ghostty" {
+        return Some(Protocol::Kitty);
+    }
+    if program == "iTerm.app" || program == "WezTerm" {
+        return Some(Protocol::ITerm);
+    }
+    if term.contains("sixel") || term == "foot" || term == "mlterm" {
+        return Some(Protocol::Sixel);
+    }
+    None
 }
 
 impl UrlConsoleQRCode {
@@ -27,19 +59,19 @@ impl UrlConsoleQRCode {
         Self {
             qrcode_id: qrcode_id.to_string(),
             data: None,
+            image: Vec::new(),
+            viewer_file: None,
         }
     }
-    pub fn download_file(&mut self) -> Result<(), Error> {
+    /// 在内存中下载并解码二维码图片
+    pub fn load(&mut self) -> Result<(), Error> {
         let url = format!(
             "https://ids.xmu.edu.cn/authserver/qrCode/getCode?uuid={}",
             self.qrcode_id
         );
-        match download_file(&DownloadFile::new(&url, QRCODE_PATH)) {
-            Ok(_) => {}
-            Err(_) => return Err(Error::Network),
-        };
+        let image = send(client().get(&url))?.bytes().to_vec();
 
-        let img = match image::open(QRCODE_PATH) {
+        let img = match image::load_from_memory(&image) {
             Ok(e) => e,
             Err(_) => return Err(Error::OpenQRCode),
         };
@@ -53,27 +85,144 @@ impl UrlConsoleQRCode {
             Some(e) => e,
             None => return Err(Error::OpenQRCode),
         });
+        self.image = image;
 
         Ok(())
     }
-    pub fn show(&mut self) -> Result<(), Error> {
+    fn code(&mut self) -> Result<QrCode, Error> {
         if self.data.is_none() {
-            self.download_file()?;
+            self.load()?;
+        }
+        match QrCode::new(self.data.as_ref().unwrap_or(&String::new()).as_bytes()) {
+            Ok(e) => Ok(e),
+            Err(_) => Err(Error::OpenQRCode),
+        }
+    }
+    /// 按设置渲染成字符，全屏界面等只能显示文字的地方使用。
+    /// 深色背景的终端中 `█` 显示为浅色，所以默认把浅色模块和四周的空白画成 `█`
+    pub fn render(&mut self) -> Result<String, Error> {
+        let config = config::get();
+        let code = self.code()?;
+        let light_terminal = config.qrcode_invert;
+        Ok(match config.qrcode_mode {
+            QrMode::Compact => {
+                let (dark, light) = match light_terminal {
+                    true => (Dense1x2::Dark, Dense1x2::Light),
+                    false => (Dense1x2::Light, Dense1x2::Dark),
+                };
+                code.render::<Dense1x2>()
+                    .dark_color(dark)
+                    .light_color(light)
+                    .build()
+            }
+            _ => {
+                let (dark, light) = match light_terminal {
+                    true => ('█', ' '),
+                    false => (' ', '█'),
+                };
+                code.render::<char>()
+                    .dark_color(dark)
+                    .light_color(light)
+                    .module_dimensions(2, 1)
+                    .build()
+            }
+        })
+    }
+    /// 按设置在终端中展示二维码
+    pub fn show(&mut self) -> Result<(), Error> {
+        let mode = config::get().qrcode_mode;
+        let shown = match mode {
+            QrMode::Auto | QrMode::Inline => match detect_protocol() {
+                Some(protocol) => self.show_inline(protocol)?,
+                None => {
+                    if mode == QrMode::Inline {
+                        warn!("当前终端不支持图片协议，改用字符显示");
+                    }
+                    false
+                }
+            },
+            QrMode::Viewer => self.open_viewer()?,
+            _ => false,
+        };
+        if !shown {
+            println!("{}", self.render()?);
         }
-        let code = QrCode::new(self.data.as_ref().unwrap_or(&String::new()).as_bytes()).unwrap();
-        let string = code
-            .render::<char>()
-            .dark_color('█')
-            .light_color(' ')
-            .quiet_zone(false)
-            .module_dimensions(2, 1)
-            .build();
-        println!("{}", string);
         Ok(())
     }
+    fn show_inline(&mut self, protocol: Protocol) -> Result<bool, Error> {
+        self.code()?;
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&self.image);
+        let mut out = stdout();
+        match protocol {
+            Protocol::Kitty => {
+                let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    let head = match i {
+                        0 => format!("f=100,a=T,m={}", more),
+                        _ => format!("m={}", more),
+                    };
+                    write!(
+                        out,
+                        "\x1b_G{};{}\x1b\\",
+                        head,
+                        String::from_utf8_lossy(chunk)
+                    )?;
+                }
+            }
+            Protocol::ITerm => {
+                write!(
+                    out,
+                    "\x1b]1337;File=inline=1;size={}:{}\x07",
+                    self.image.len(),
+                    encoded
+                )?;
+            }
+            Protocol::Sixel => {
+                let code = self.code()?;
+                write!(out, "{}", sixel(&code))?;
+            }
+        }
+        writeln!(out)?;
+        out.flush()?;
+        Ok(true)
+    }
+    fn open_viewer(&mut self) -> Result<bool, Error> {
+        self.code()?;
+        let path = temp_dir().join(format!("xmu_qrcode_{}.png", self.qrcode_id));
+        File::create(&path)?.write_all(&self.image)?;
+        let ret = if cfg!(target_os = "windows") {
+            Command::new("cmd")
+                .args(["/C", "start", ""])
+                .arg(&path)
+                .spawn()
+        } else if cfg!(target_os = "macos") {
+            Command::new("open").arg(&path).spawn()
+        } else {
+            Command::new("xdg-open").arg(&path).spawn()
+        };
+        self.viewer_file = Some(path);
+        match ret {
+            Ok(_) => {
+                debug!("已在图片查看器中打开二维码");
+                Ok(true)
+            }
+            Err(e) => {
+                warn!("无法打开图片查看器 {}，改用字符显示", e);
+                Ok(false)
+            }
+        }
+    }
+    fn remove_viewer_file(&mut self) {
+        if let Some(path) = self.viewer_file.take() {
+            remove_file(path).unwrap_or_default();
+        }
+    }
     pub fn renew(&mut self, qrcode_id: &str) {
+        self.remove_viewer_file();
         self.qrcode_id = qrcode_id.to_string();
         self.data = None;
+        self.image.clear();
     }
     pub fn get_id(&self) -> &str {
         &self.qrcode_id
@@ -94,8 +243,8 @@ impl UrlConsoleQRCode {
             self.qrcode_id,
         );
 
-        let response = Client::new().get(&url).send()?;
-        let state = response.text()?;
+        let response = send(client().get(&url))?;
+        let state = response.text();
         match state.as_str() {
             "0" => Ok(Some(State::Waiting)),
             "1" => Ok(Some(State::Success)),
@@ -106,10 +255,62 @@ impl UrlConsoleQRCode {
     }
 }
 
+/// 把二维码编码成两色的 sixel 图像，四周留出两个模块的空白。
+/// sixel 画的是真实的颜色，与终端背景无关，不受反色设置影响
+fn sixel(code: &QrCode) -> String {
+    let width = code.width();
+    let colors = code.to_colors();
+    let size = (width + 4) * SIXEL_SCALE;
+    let is_dark = |x: usize, y: usize| {
+        let (mx, my) = (x / SIXEL_SCALE, y / SIXEL_SCALE);
+        if mx < 2 || my < 2 || mx >= width + 2 || my >= width + 2 {
+            return false;
+        }
+        colors[(my - 2) * width + mx - 2] == Color::Dark
+    };
+    let mut ret = format!("\x1bPq\"1;1;{};{}#0;2;100;100;100#1;2;0;0;0", size, size);
+    for band in (0..size).step_by(6) {
+        for color in [0, 1] {
+            ret.push_str(&format!("#{}", color));
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..size {
+                let mut bits = 0u8;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < size && is_dark(x, y) == (color == 1) {
+                        bits |= 1 << dy;
+                    }
+                }
+                let c = (63 + bits) as char;
+                run = match run {
+                    Some((last, n)) if last == c => Some((last, n + 1)),
+                    Some((last, n)) => {
+                        push_run(&mut ret, last, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((last, n)) = run {
+                push_run(&mut ret, last, n);
+            }
+            ret.push('$');
+        }
+        ret.push('-');
+    }
+    ret.push_str("\x1b\\");
+    ret
+}
+
+fn push_run(out: &mut String, c: char, n: usize) {
+    match n {
+        1..=3 => out.extend(std::iter::repeat_n(c, n)),
+        _ => out.push_str(&format!("!{}{}", n, c)),
+    }
+}
+
 impl Drop for UrlConsoleQRCode {
     fn drop(&mut self) {
-        if self.data.is_some() {
-            remove_file(QRCODE_PATH).unwrap_or_default();
-        }
+        self.remove_viewer_file();
     }
 }
@@ -1,22 +1,43 @@
 use super::main::Error;
-use reqwest::blocking::{Client, Response};
-use reqwest::header::{HeaderMap, REFERER, USER_AGENT};
-use reqwest::IntoUrl;
+use crate::public::http::{builder, send_in_session, Response};
+use crate::public::webvpn;
+use chrono::DateTime;
+use cookie_store::CookieExpiration;
+use reqwest::header::{HeaderMap, REFERER};
+use reqwest::{Client, IntoUrl, Url};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use std::sync::Arc;
 
+/// 登录过程中使用的会话，cookie 单独保存，不影响共享的客户端
 pub struct SessionClient {
     client: Client,
     headers: HeaderMap,
+    cookies: Arc<CookieStoreMutex>,
 }
 
 impl SessionClient {
     pub fn new() -> Self {
-        let client = Client::builder().cookie_store(true).build().unwrap();
-        let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT,"Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.3".parse().unwrap());
-        Self { client, headers }
+        let mut store = CookieStore::default();
+        // 开启 WebVPN 时所有请求都经过门户，先放入门户的登录凭据
+        if let (true, Some(cookie)) = (webvpn::active(), webvpn::cookie()) {
+            let url = Url::parse(&format!("https://{}/", webvpn::HOST)).unwrap();
+            for pair in cookie.split(';') {
+                store.parse(pair.trim(), &url).ok();
+            }
+        }
+        let cookies = Arc::new(CookieStoreMutex::new(store));
+        let client = builder()
+            .cookie_provider(Arc::clone(&cookies))
+            .build()
+            .unwrap();
+        Self {
+            client,
+            headers: HeaderMap::new(),
+            cookies,
+        }
     }
     pub fn get<U: IntoUrl>(&mut self, url: U) -> Result<Response, Error> {
-        let ret = self.client.get(url).headers(self.headers.clone()).send()?;
+        let ret = send_in_session(self.client.get(url).headers(self.headers.clone()))?;
         self.headers
             .insert(REFERER, ret.url().as_str().parse().unwrap());
         Ok(ret)
@@ -26,14 +47,44 @@ impl SessionClient {
         url: U,
         data: &T,
     ) -> Result<Response, Error> {
-        let ret = self
-            .client
-            .post(url)
-            .headers(self.headers.clone())
-            .form(data)
-            .send()?;
+        let ret = send_in_session(
+            self.client
+                .post(url)
+                .headers(self.headers.clone())
+                .form(data),
+        )?;
         self.headers
             .insert(REFERER, ret.url().as_str().parse().unwrap());
         Ok(ret)
     }
+    pub fn get_cookie_header(&self, url: &str) -> Option<String> {
+        let mut url = Url::parse(url).ok()?;
+        webvpn::rewrite(&mut url);
+        let lock = self.cookies.lock().unwrap();
+        let header = lock
+            .get_request_values(&url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        match header.is_empty() {
+            true => None,
+            false => Some(header),
+        }
+    }
+    /// 取出某个 cookie 的值和过期时间（UTC）
+    pub fn get_cookie(
+        &self,
+        url: &str,
+        name: &str,
+    ) -> Option<(String, Option<DateTime<chrono::Utc>>)> {
+        let mut url = Url::parse(url).ok()?;
+        webvpn::rewrite(&mut url);
+        let lock = self.cookies.lock().unwrap();
+        let cookie = lock.matches(&url).into_iter().find(|x| x.name() == name)?;
+        let expires = match &cookie.expires {
+            CookieExpiration::AtUtc(t) => DateTime::from_timestamp(t.unix_timestamp(), 0),
+            CookieExpiration::SessionEnd => None,
+        };
+        Some((cookie.value().to_string(), expires))
+    }
 }
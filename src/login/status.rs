@@ -0,0 +1,155 @@
+use chrono::{Duration, NaiveDateTime};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::profile;
+use crate::course_downloader::download::get_with_cookie;
+use crate::public::ical::now;
+
+/// 主菜单标题中缓存的状态多久后重新检查
+const RECHECK_SECS: u64 = 300;
+/// 距离过期不足这个时间时提醒重新登录
+const EXPIRE_WARN_HOURS: i64 = 24;
+
+lazy_static! {
+    /// 上次检查的 session 和结果
+    static ref CACHE: Mutex<Option<(Instant, Option<String>, Status)>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone)]
+pub struct UserInfo {
+    pub name: String,
+    pub user_no: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    NotLoggedIn,
+    Invalid,
+    Unknown,
+    Valid(UserInfo),
+}
+
+fn age(since: &NaiveDateTime) -> String {
+    let duration = now() - *since;
+    match duration.num_days() {
+        0 => format!(
+            "{} 小时 {} 分钟",
+            duration.num_hours(),
+            duration.num_minutes() % 60
+        ),
+        days => format!("{} 天 {} 小时", days, duration.num_hours() % 24),
+    }
+}
+
+/// 用当前 session 请求用户信息
+pub fn check() -> Status {
+    let session = match profile::current().session {
+        Some(v) => v,
+        None => return Status::NotLoggedIn,
+    };
+    let resp = match get_with_cookie(
+        "https://lnt.xmu.edu.cn/api/profile",
+        &format!("session={}", session),
+    ) {
+        Ok(v) => v,
+        Err(_) => return Status::Unknown,
+    };
+    if matches!(
+        resp.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Status::Invalid;
+    }
+    let json: Value = match resp.json() {
+        Ok(v) => v,
+        Err(_) => return Status::Invalid,
+    };
+    let field = |k: &str| match json.get(k) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => String::new(),
+    };
+    match field("name").as_str() {
+        "" => Status::Invalid,
+        name => Status::Valid(UserInfo {
+            name: name.to_string(),
+            user_no: field("user_no"),
+        }),
+    }
+}
+
+/// 检查结果，session 未变化时在一段时间内复用
+fn cached() -> Status {
+    let session = profile::current().session;
+    let mut lock = CACHE.lock().unwrap();
+    if let Some((time, last, status)) = lock.as_ref() {
+        if *last == session && time.elapsed().as_secs() < RECHECK_SECS {
+            return status.clone();
+        }
+    }
+    let status = check();
+    *lock = Some((Instant::now(), session, status.clone()));
+    status
+}
+
+/// session 即将过期时的提醒
+fn expire_warning() -> Option<String> {
+    let expires = profile::current().session_expires?;
+    let left = expires - now();
+    if left <= Duration::zero() {
+        Some("session 已过期".to_string())
+    } else if left < Duration::hours(EXPIRE_WARN_HOURS) {
+        Some(format!(
+            "session 将在 {} 小时 {} 分钟后过期",
+            left.num_hours(),
+            left.num_minutes() % 60
+        ))
+    } else {
+        None
+    }
+}
+
+/// 主菜单标题上显示的一行状态
+pub fn header() -> String {
+    let name = profile::current_name();
+    let status = match cached() {
+        Status::NotLoggedIn => "未登录".to_string(),
+        Status::Invalid => "登录已失效，请重新登录".to_string(),
+        Status::Unknown => "无法检查登录状态".to_string(),
+        Status::Valid(user) => format!("{} {}", user.name, user.user_no),
+    };
+    match expire_warning() {
+        Some(warning) => format!("[{}] {}，{}", name, status, warning),
+        None => format!("[{}] {}", name, status),
+    }
+}
+
+pub fn main() {
+    let profile = profile::current();
+    info!("当前账号：{}", profile::current_name());
+    match check() {
+        Status::NotLoggedIn => warn!("还没有登录课程中心"),
+        Status::Invalid => warn!("登录已失效，请重新登录"),
+        Status::Unknown => warn!("网络不通，无法检查登录状态"),
+        Status::Valid(user) => info!("已登录：{} 学号 {}", user.name, user.user_no),
+    }
+    if let Some(saved_at) = profile.session_saved_at {
+        info!("session 获取于 {}，已使用 {}", saved_at, age(&saved_at));
+    }
+    match profile.session_expires {
+        Some(expires) => info!("session 过期时间 {}", expires),
+        None => info!("session 没有标明过期时间"),
+    }
+    if let Some(warning) = expire_warning() {
+        warn!("{}，请尽快重新登录", warning);
+    }
+    match profile.jw_session {
+        Some(_) => info!("已保存教务系统 session"),
+        None => info!("还没有登录教务系统"),
+    }
+}
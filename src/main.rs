@@ -1,18 +1,61 @@
+mod announcements;
+mod api;
+mod archive;
 mod course_downloader;
+mod daemon;
+mod deadlines;
+mod exams;
+mod grades;
+mod jw;
 mod login;
+mod metadata;
 mod public;
 mod setting;
+mod submit;
+mod tui;
 
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Select;
 fn main() {
     public::main();
+    if let Some(name) = login::profile::from_args() {
+        login::profile::switch(&name);
+    }
+    if std::env::args().any(|x| x == "--status") {
+        login::status::main();
+        return;
+    }
+    if std::env::args().any(|x| x == "--serve") {
+        api::main::serve();
+        return;
+    }
+    if std::env::args().any(|x| x == "--daemon") {
+        daemon::main::run_forever();
+        return;
+    }
+    announcements::main::startup();
+    if std::env::args().any(|x| x == "--tui") {
+        tui::main();
+        return;
+    }
     loop {
         let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("选择功能")
+            .with_prompt(format!("{}  选择功能", login::status::header()))
             .default(0)
             .item("下载文件")
             .item("登录账号")
+            .item(login::profile::menu_label())
+            .item("账号状态")
+            .item("查询成绩")
+            .item("考试安排")
+            .item("作业截止")
+            .item("提交作业")
+            .item(announcements::main::menu_label())
+            .item("全屏界面")
+            .item("后台同步")
+            .item("本地接口")
+            .item("归档课程")
+            .item("导出课程数据")
             .item("设置")
             .item("重试失败任务")
             .item("退出")
@@ -20,10 +63,31 @@ fn main() {
             .unwrap_or(1000);
         match selection {
             0 => course_downloader::main(),
-            1 => login::main(),
-            2 => setting::main(),
-            3 => public::download_file::retry_error_tasks(),
+            // 登录或切换账号后课程中心的会话变了，重新获取公告
+            1 => refresh_after(login::main),
+            2 => refresh_after(login::profile::main),
+            3 => login::status::main(),
+            4 => grades::main(),
+            5 => exams::main(),
+            6 => deadlines::main(),
+            7 => submit::main(),
+            8 => announcements::main(),
+            9 => tui::main(),
+            10 => daemon::main(),
+            11 => api::main(),
+            12 => archive::main(),
+            13 => metadata::main(),
+            14 => setting::main(),
+            15 => public::download_file::retry_error_tasks(),
             _ => break,
         }
     }
 }
+
+fn refresh_after(f: fn()) {
+    let before = login::profile::current().session;
+    f();
+    if login::profile::current().session != before {
+        announcements::main::startup();
+    }
+}
@@ -0,0 +1,195 @@
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Select;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::course_downloader::course::{
+    get_activities, get_cookie, load_courses, upload_path, Course,
+};
+use crate::course_downloader::main::{download_path, Error};
+use crate::course_downloader::search::select_courses;
+use crate::course_downloader::video::{self, is_video};
+use crate::public::logger::Logger;
+use crate::public::manifest::locate;
+use crate::public::storage::{write_csv, write_json, EXPORT_PATH};
+use crate::public::VOID_VEC;
+
+pub fn main() {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("导出课程数据（JSON 和 CSV）")
+        .default(0)
+        .item("导出课程列表")
+        .item("导出选择的课程的活动和文件")
+        .item("导出全部课程的活动和文件")
+        .item("返回")
+        .interact()
+        .unwrap_or(1000);
+    let ret = match selection {
+        0 => export_courses(),
+        1 => get_cookie()
+            .and_then(|cookie| select_courses(&cookie).map(|x| (cookie, x)))
+            .and_then(|(cookie, courses)| export_activities(&courses, &cookie)),
+        2 => get_cookie()
+            .and_then(|cookie| load_courses(&cookie, false).map(|x| (cookie, x)))
+            .and_then(|(cookie, courses)| export_activities(&courses, &cookie)),
+        _ => Ok(()),
+    };
+    match ret {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+fn save_json(name: &str, value: &Value) {
+    let path = format!("{}{}.json", EXPORT_PATH, name);
+    match write_json(&path, value) {
+        Ok(_) => info!("已导出到 {}", path),
+        Err(e) => warn!("导出失败 {} {}", path, e),
+    }
+}
+
+fn save_csv(name: &str, headers: &[&str], rows: &[Vec<String>]) {
+    let path = format!("{}{}.csv", EXPORT_PATH, name);
+    match write_csv(&path, headers, rows) {
+        Ok(_) => info!("已导出到 {}", path),
+        Err(e) => warn!("导出失败 {} {}", path, e),
+    }
+}
+
+fn export_courses() -> Result<(), Error> {
+    let courses = load_courses(&get_cookie()?, true)?;
+    save_json("courses", &json!(courses));
+    let rows = courses
+        .iter()
+        .map(|x| {
+            vec![
+                x.id.clone(),
+                x.name.clone(),
+                x.instructors.clone(),
+                x.semester.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    save_csv("courses", &["id", "name", "instructors", "semester"], &rows);
+    Ok(())
+}
+
+/// 数字和字符串都转成不带引号的文本
+fn to_string(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(v)) => v.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(v) => v.to_string(),
+    }
+}
+
+/// 整理后的活动，`raw` 保留接口返回的原始数据
+fn activity_json(element: &Value, path: &str) -> Value {
+    let uploads = element
+        .get("uploads")
+        .and_then(|x| x.as_array())
+        .unwrap_or(&*VOID_VEC)
+        .iter()
+        .map(|upload| {
+            let reference = upload.get("reference_id").unwrap_or(&Value::Null);
+            json!({
+                "reference_id": reference,
+                "name": to_string(upload.get("name")),
+                "size": upload.get("size").and_then(|x| x.as_u64()),
+                "local": locate(&reference.to_string(), &upload_path(upload, path)),
+            })
+        })
+        .collect::<Vec<_>>();
+    let video = match is_video(element) {
+        true => json!({ "local": video::local_path(element, path) }),
+        false => Value::Null,
+    };
+    json!({
+        "id": element.get("id"),
+        "title": to_string(element.get("title")),
+        "type": to_string(element.get("type")),
+        "start_time": element.get("start_time"),
+        "end_time": element.get("end_time"),
+        "uploads": uploads,
+        "video": video,
+        "raw": element,
+    })
+}
+
+fn export_activities(courses: &[Course], cookie: &str) -> Result<(), Error> {
+    let path = download_path();
+    let mut tree = Vec::new();
+    let mut activity_rows = Vec::new();
+    let mut upload_rows = Vec::new();
+    for (i, course) in courses.iter().enumerate() {
+        info!("({}/{}) 获取 {} 的活动", i + 1, courses.len(), course.name);
+        // 一门课程失败时跳过，已经获取的课程照样导出
+        let activities = match get_activities(&course.id, cookie) {
+            Ok(v) => v
+                .iter()
+                .map(|x| activity_json(x, &path))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                warn!("获取 {} 的活动失败，已跳过", course.name);
+                e.logger();
+                continue;
+            }
+        };
+        for activity in activities.iter() {
+            let uploads = activity["uploads"].as_array().unwrap_or(&*VOID_VEC);
+            activity_rows.push(vec![
+                course.id.clone(),
+                course.name.clone(),
+                to_string(activity.get("id")),
+                to_string(activity.get("title")),
+                to_string(activity.get("type")),
+                to_string(activity.get("start_time")),
+                to_string(activity.get("end_time")),
+                uploads.len().to_string(),
+            ]);
+            for upload in uploads {
+                upload_rows.push(vec![
+                    course.id.clone(),
+                    course.name.clone(),
+                    to_string(activity.get("id")),
+                    to_string(activity.get("title")),
+                    to_string(upload.get("reference_id")),
+                    to_string(upload.get("name")),
+                    to_string(upload.get("size")),
+                    to_string(upload.get("local")),
+                ]);
+            }
+        }
+        tree.push(json!({ "course": course, "activities": activities }));
+    }
+    save_json("activities", &json!(tree));
+    save_csv(
+        "activities",
+        &[
+            "course_id",
+            "course",
+            "activity_id",
+            "title",
+            "type",
+            "start_time",
+            "end_time",
+            "uploads",
+        ],
+        &activity_rows,
+    );
+    save_csv(
+        "uploads",
+        &[
+            "course_id",
+            "course",
+            "activity_id",
+            "activity",
+            "reference_id",
+            "name",
+            "size",
+            "local",
+        ],
+        &upload_rows,
+    );
+    Ok(())
+}
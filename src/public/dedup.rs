@@ -0,0 +1,280 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{copy, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::public::progress::format_size;
+use crate::public::storage::{read_json, write_json, DATA_PATH};
+use crate::setting::config::{self, DedupMode};
+
+lazy_static! {
+    static ref INDEX: Mutex<Index> = Mutex::new(load());
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Entry {
+    size: u64,
+    /// 修改时间（秒），和大小一起判断文件是否变过
+    modified: u64,
+    hash: String,
+    /// 已经被替换为这个文件的硬链接
+    link: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Index {
+    files: BTreeMap<String, Entry>,
+    /// 课程中心 upload 的 reference_id 和下载到的位置
+    references: BTreeMap<String, String>,
+    /// 硬链接合并累计节省的空间
+    reclaimed: u64,
+}
+
+fn index_path() -> String {
+    format!("{}dedup.json", DATA_PATH)
+}
+
+fn load() -> Index {
+    read_json(&index_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+fn save(index: &Index) {
+    let ret = serde_json::to_value(index)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&index_path(), &x));
+    if let Err(e) = ret {
+        warn!("保存去重索引失败 {}", e);
+    }
+}
+
+fn metadata(path: &str) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+    Some((meta.len(), modified))
+}
+
+pub fn hash_file(path: &str) -> Result<String> {
+    let mut hasher = Sha256::new();
+    copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 计算文件的哈希，大小和修改时间没变时直接使用索引中的结果
+fn entry(index: &Index, path: &str) -> Result<Entry> {
+    let (size, modified) = metadata(path).ok_or_else(|| anyhow::anyhow!("文件不存在"))?;
+    if let Some(old) = index.files.get(path) {
+        if old.size == size && old.modified == modified {
+            return Ok(old.clone());
+        }
+    }
+    Ok(Entry {
+        size,
+        modified,
+        hash: hash_file(path)?,
+        link: None,
+    })
+}
+
+/// 内容相同、仍然存在的另一个文件，已经链接到 path 的不算
+fn find_same(index: &Index, path: &str, entry: &Entry) -> Option<String> {
+    index
+        .files
+        .iter()
+        .filter(|(p, e)| p.as_str() != path && e.hash == entry.hash && e.size == entry.size)
+        .filter(|(_, e)| e.link.as_deref() != Some(path))
+        .map(|(p, e)| e.link.clone().unwrap_or(p.clone()))
+        .find(|p| p != path && metadata(p).map(|(size, _)| size) == Some(entry.size))
+}
+
+/// 用 source 的硬链接替换 target，先链接到临时文件再改名，失败时 target 不变
+fn link(source: &str, target: &str) -> Result<()> {
+    if let Some(parent) = Path::new(target).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = format!("{}.dedup", target);
+    fs::remove_file(&temp).unwrap_or_default();
+    fs::hard_link(source, &temp)?;
+    if let Err(e) = fs::rename(&temp, target) {
+        fs::remove_file(&temp).unwrap_or_default();
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// 登记一个文件并按设置处理重复
+fn check(index: &mut Index, path: &str, mode: DedupMode) -> Result<()> {
+    let mut entry = entry(index, path)?;
+    let same = match entry.link {
+        Some(_) => None,
+        None => find_same(index, path, &entry),
+    };
+    if let Some(source) = &same {
+        match mode {
+            DedupMode::HardLink => match link(source, path) {
+                Ok(_) => {
+                    info!("{} 与 {} 相同，已合并为硬链接", path, source);
+                    index.reclaimed += entry.size;
+                    entry.link = Some(source.clone());
+                    entry.modified = metadata(path).map(|x| x.1).unwrap_or(entry.modified);
+                }
+                Err(e) => warn!("无法创建硬链接 {} {}", path, e),
+            },
+            _ => info!("{} 与 {} 内容相同", path, source),
+        }
+    }
+    index.files.insert(path.to_string(), entry);
+    Ok(())
+}
+
+/// 下载完成后调用，会读取整个文件，不要在异步任务中直接调用
+pub fn after_download(path: &str) {
+    let mode = config::get().dedup;
+    if mode == DedupMode::Off {
+        return;
+    }
+    let mut index = INDEX.lock().unwrap();
+    match check(&mut index, path, mode) {
+        Ok(_) => save(&index),
+        Err(e) => warn!("计算文件哈希失败 {} {}", path, e),
+    }
+}
+
+/// 下载前调用，同一个 upload 已经下载过时直接链接过去，返回 true 表示不用再下载
+pub fn link_reference(reference_id: &str, size: u64, target: &str) -> bool {
+    let mut index = INDEX.lock().unwrap();
+    // 目标位置已有文件时交给文件名冲突的设置处理
+    let source = match index.references.get(reference_id) {
+        Some(v) if v != target && !Path::new(target).exists() => v.clone(),
+        _ => return false,
+    };
+    // 课程中心没有给出大小时只要文件存在就认为相同
+    let exists = metadata(&source).is_some_and(|(len, _)| size == 0 || len == size);
+    if config::get().dedup != DedupMode::HardLink || !exists {
+        return false;
+    }
+    let mut entry = match entry(&index, &source) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    if let Err(e) = link(&source, target) {
+        warn!("无法创建硬链接 {} {}", target, e);
+        return false;
+    }
+    info!("{} 已下载过，链接到 {}", target, source);
+    index
+        .files
+        .entry(source.clone())
+        .or_insert_with(|| entry.clone());
+    index.reclaimed += entry.size;
+    entry.link = Some(source);
+    index.files.insert(target.to_string(), entry);
+    save(&index);
+    true
+}
+
+pub fn record_reference(reference_id: &str, target: &str) {
+    let mut index = INDEX.lock().unwrap();
+    index
+        .references
+        .insert(reference_id.to_string(), target.to_string());
+    save(&index);
+}
+
+fn walk(dir: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match path.is_dir() {
+            true => walk(&path, files),
+            false => files.push(path.to_string_lossy().to_string()),
+        }
+    }
+}
+
+/// 扫描目录中已有的文件并按设置处理重复
+pub fn scan(dir: &str) {
+    let mode = config::get().dedup;
+    let mut files = Vec::new();
+    walk(Path::new(dir), &mut files);
+    files.sort();
+    info!("扫描 {} 个文件", files.len());
+    let mut index = INDEX.lock().unwrap();
+    for file in files
+        .iter()
+        .filter(|x| !x.ends_with(".dedup") && !x.ends_with(".part"))
+    {
+        if let Err(e) = check(&mut index, file, mode) {
+            warn!("计算文件哈希失败 {} {}", file, e);
+        }
+    }
+    save(&index);
+}
+
+pub struct Group {
+    pub size: u64,
+    pub files: Vec<String>,
+    /// 其中还没有合并的文件占用的多余空间
+    pub wasted: u64,
+}
+
+/// 索引中内容相同的文件，顺便清理已经删除的文件
+pub fn report() -> (Vec<Group>, u64) {
+    let mut index = INDEX.lock().unwrap();
+    index.files.retain(|path, _| Path::new(path).exists());
+    save(&index);
+    let mut groups: HashMap<&str, Vec<(&String, &Entry)>> = HashMap::new();
+    for (path, entry) in index.files.iter() {
+        groups.entry(&entry.hash).or_default().push((path, entry));
+    }
+    let mut ret = groups
+        .into_values()
+        .filter(|x| x.len() > 1)
+        .map(|x| {
+            let size = x[0].1.size;
+            let copies = x.iter().filter(|(_, e)| e.link.is_none()).count() as u64;
+            Group {
+                size,
+                files: x.iter().map(|(p, _)| p.to_string()).collect(),
+                wasted: size * copies.saturating_sub(1),
+            }
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| b.wasted.cmp(&a.wasted).then(b.size.cmp(&a.size)));
+    (ret, index.reclaimed)
+}
+
+pub fn print_report() {
+    let (groups, reclaimed) = report();
+    for group in groups.iter() {
+        println!("{}（{} 份）", format_size(group.size), group.files.len());
+        for file in group.files.iter() {
+            println!("    {}", file);
+        }
+    }
+    let wasted = groups.iter().map(|x| x.wasted).sum::<u64>();
+    info!(
+        "共 {} 组重复文件，还可以节省 {}，已通过硬链接节省 {}",
+        groups.len(),
+        format_size(wasted),
+        format_size(reclaimed)
+    );
+}
@@ -1,69 +1,367 @@
-use crate::public::thread_manage;
-use anyhow::Result;
-use curl::easy::Easy;
+use crate::public::dedup;
+use crate::public::filetype;
+use crate::public::hls;
+use crate::public::http::{client, execute, runtime, timed};
+use crate::public::limit;
+use crate::public::manifest::{self, Action};
+use crate::public::notify::{notify, Event};
+use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use log::{debug, info, trace, warn};
+use reqwest::header::{HeaderName, CONTENT_DISPOSITION, CONTENT_TYPE};
+use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::fs::File;
-use std::io::Write;
-use std::sync::{Arc, Condvar, Mutex};
-use threadpool::ThreadPool;
+use std::fs::{remove_file, rename};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+/// 判断文件类型时读取的开头长度
+const MAGIC_LEN: usize = 16;
 
 lazy_static! {
-    static ref download_queue: Arc<Mutex<VecDeque<DownloadFile>>> =
-        Arc::new(Mutex::new(VecDeque::new()));
-    static ref error_queue: Arc<Mutex<VecDeque<DownloadFile>>> =
-        Arc::new(Mutex::new(VecDeque::new()));
-    static ref condvar: Arc<Condvar> = Arc::new(Condvar::new());
-    static ref pool: Arc<Mutex<ThreadPool>> =
-        Arc::new(Mutex::new(ThreadPool::with_name("下载线程".to_string(), 4)));
+    static ref download_queue: Mutex<VecDeque<DownloadFile>> = Mutex::new(VecDeque::new());
+    static ref error_queue: Mutex<VecDeque<DownloadFile>> = Mutex::new(VecDeque::new());
+    static ref active_queue: Mutex<Vec<TaskState>> = Mutex::new(Vec::new());
+    static ref batch: Mutex<Batch> = Mutex::new(Batch::default());
+    /// 有新任务放入队列时唤醒调度
+    static ref wakeup: Notify = Notify::new();
+    /// 同时下载的数量，调度前先取得一个许可
+    static ref permits: Arc<Semaphore> = Arc::new(Semaphore::new(DEFAULT_CONCURRENCY));
+    static ref concurrency: Mutex<Concurrency> = Mutex::new(Concurrency {
+        target: DEFAULT_CONCURRENCY,
+        debt: 0,
+    });
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// `track` 期间当前线程放入队列的任务
+    static TRACKED: RefCell<Option<Vec<u64>>> = const { RefCell::new(None) };
 }
 
 #[derive(Default, Clone, Debug)]
 pub struct DownloadFile {
+    /// 区分保存到同一个位置的任务
+    pub id: u64,
     pub url: String,
     pub file: String,
+    /// 课程中心的 reference_id，用来判断同名文件是否相同
+    pub reference: String,
+    /// 预期的大小，0 为未知
+    pub size: u64,
+    action: Action,
+    original: Option<String>,
+    sync: bool,
 }
 
 impl DownloadFile {
     pub fn new(url: &str, file: &str) -> Self {
         Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             url: url.to_string(),
             file: file.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn with_reference(mut self, reference: &str, size: u64) -> Self {
+        self.reference = reference.to_string();
+        self.size = size;
+        self
+    }
+    pub fn is_sync(&self) -> bool {
+        self.sync
+    }
+    /// 同步时内容相同的文件总是跳过，即使设置为覆盖
+    pub fn for_sync(mut self) -> Self {
+        self.sync = true;
+        self
+    }
+    fn apply(&mut self, file: String, action: Action) {
+        if file != self.file {
+            self.original = Some(std::mem::replace(&mut self.file, file));
+        }
+        // 重试时保留第一次放入队列时的处理方式
+        if action != Action::New {
+            self.action = action;
         }
     }
-    pub fn run(self) {
-        debug!("放入任务队列 {:?}", &self);
-        let mut lock = download_queue.lock().unwrap();
-        lock.push_back(self);
-        condvar.notify_one();
+    /// 按文件名冲突的设置放入队列，返回最终保存的位置，跳过时返回 None
+    pub fn run(mut self) -> Option<String> {
+        let mut queue = download_queue.lock().unwrap();
+        let (file, action) = manifest::resolve(&self, &pending(&queue))?;
+        self.apply(file.clone(), action);
+        push(&mut queue, self);
+        drop(queue);
+        wakeup.notify_one();
+        Some(file)
+    }
+    /// 只按文件名冲突的设置决定保存的位置，不用下载时返回 None。
+    /// 下载地址需要请求接口才能拿到时，先用这个判断，再设置 `url` 后 `queue`
+    pub fn plan(mut self) -> Option<Self> {
+        let (file, action) = manifest::resolve(&self, &pending(&download_queue.lock().unwrap()))?;
+        self.apply(file, action);
+        Some(self)
+    }
+    /// 放入 `plan` 过的任务，期间有同名的任务放入队列时重新处理
+    pub fn queue(self) -> Option<String> {
+        let mut queue = download_queue.lock().unwrap();
+        if pending(&queue).iter().any(|x| x.file == self.file) {
+            drop(queue);
+            return self.run();
+        }
+        let file = self.file.clone();
+        push(&mut queue, self);
+        drop(queue);
+        wakeup.notify_one();
+        Some(file)
     }
 }
 
-pub fn download_file(task: &DownloadFile) -> Result<()> {
-    let mut curl = Easy::new();
-    let mut output = File::create(&task.file)?;
-    curl.url(&task.url)?;
-    curl.progress(true)?;
-    curl.progress_function(
-        |total_download_bytes, cur_download_bytes, _total_upload_bytes, _cur_upload_bytes| {
-            if total_download_bytes > 0.0 {
-                trace!("已下载:{}/{}", cur_download_bytes, total_download_bytes);
-            }
-            true
-        },
-    )?;
-    curl.write_function(move |data: &[u8]| {
-        output.write_all(data).unwrap();
-        Ok(data.len())
-    })?;
-    curl.perform()?;
+fn push(queue: &mut VecDeque<DownloadFile>, task: DownloadFile) {
+    debug!("放入任务队列 {:?}", &task);
+    TRACKED.with(|x| {
+        if let Some(ids) = x.borrow_mut().as_mut() {
+            ids.push(task.id);
+        }
+    });
+    queue.push_back(task);
+}
+
+/// 执行 `f` 并返回期间当前线程放入队列的任务 id，
+/// 同时有其他地方在下载时也能只等待和统计自己的任务
+pub fn track<R>(f: impl FnOnce() -> R) -> (R, Vec<u64>) {
+    TRACKED.with(|x| x.replace(Some(Vec::new())));
+    let ret = f();
+    let ids = TRACKED.with(|x| x.take()).unwrap_or_default();
+    (ret, ids)
+}
+
+/// 已经在队列中或正在下载的任务，调用前已经锁住了任务队列
+fn pending(queue: &VecDeque<DownloadFile>) -> Vec<DownloadFile> {
+    let mut ret = queue.iter().cloned().collect::<Vec<_>>();
+    ret.extend(active_queue.lock().unwrap().iter().map(|x| x.task.clone()));
+    ret
+}
+
+/// 从队列开始有任务到再次清空期间的结果，清空时发送通知
+#[derive(Default)]
+struct Batch {
+    done: usize,
+    failed: Vec<String>,
+}
+
+fn finish_task<T>(task: &DownloadFile, ret: &Result<T>, cancelled: bool) {
+    {
+        let mut lock = batch.lock().unwrap();
+        match ret {
+            Ok(_) => lock.done += 1,
+            Err(_) if !cancelled => lock.failed.push(task.file.clone()),
+            Err(_) => {}
+        }
+    }
+    if !is_idle() {
+        return;
+    }
+    let Batch { done, failed } = std::mem::take(&mut *batch.lock().unwrap());
+    if done > 0 {
+        notify(Event::DownloadsCompleted { count: done });
+    }
+    if !failed.is_empty() {
+        notify(Event::TasksFailed { files: failed });
+    }
+}
+
+/// 已经开始和等待下载的任务，started 为 false 表示还在队列中排队
+#[derive(Clone, Debug)]
+pub struct TaskState {
+    pub task: DownloadFile,
+    pub started: bool,
+    pub current: u64,
+    pub total: u64,
+    cancel: CancellationToken,
+}
+
+fn update_state<F: FnOnce(&mut TaskState)>(id: u64, f: F) {
+    let mut lock = active_queue.lock().unwrap();
+    if let Some(state) = lock.iter_mut().find(|x| x.task.id == id) {
+        f(state);
+    }
+}
+
+/// 正在下载和等待下载的任务
+pub fn active_tasks() -> Vec<TaskState> {
+    let mut ret = active_queue.lock().unwrap().clone();
+    ret.extend(download_queue.lock().unwrap().iter().map(|task| TaskState {
+        task: task.clone(),
+        started: false,
+        current: 0,
+        total: 0,
+        cancel: CancellationToken::new(),
+    }));
+    ret
+}
+
+/// 队列中没有正在下载或等待下载的任务
+pub fn is_idle() -> bool {
+    // 与调度相同的加锁顺序
+    let queue = download_queue.lock().unwrap();
+    queue.is_empty() && active_queue.lock().unwrap().is_empty()
+}
+
+/// 这些任务都已经下载完成、失败或取消
+pub fn is_finished(ids: &[u64]) -> bool {
+    let queue = download_queue.lock().unwrap();
+    !queue.iter().any(|x| ids.contains(&x.id))
+        && !active_queue
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|x| ids.contains(&x.task.id))
+}
+
+pub fn failed_tasks() -> Vec<DownloadFile> {
+    error_queue.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn retry_error_task(index: usize) {
+    let task = error_queue.lock().unwrap().remove(index);
+    if let Some(task) = task {
+        warn!("移动到任务队列：{:?}", &task);
+        task.run();
+    }
+}
+
+/// 取消一个任务，还在排队的直接移出队列，正在下载的在下一次读写时停止
+pub fn cancel_task(id: u64) {
+    {
+        let mut queue = download_queue.lock().unwrap();
+        if let Some(index) = queue.iter().position(|x| x.id == id) {
+            let task = queue.remove(index);
+            info!("已取消 {}", task.map(|x| x.file).unwrap_or_default());
+            return;
+        }
+    }
+    update_state(id, |x| x.cancel.cancel());
+}
+
+pub fn cancel_all() {
+    download_queue.lock().unwrap().clear();
+    for state in active_queue.lock().unwrap().iter() {
+        state.cancel.cancel();
+    }
+    info!("已取消所有下载任务");
+}
+
+fn header(resp: &reqwest::Response, name: HeaderName) -> String {
+    resp.headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
+
+/// 下载中的临时文件，成功后才替换原来的文件
+fn part_path(file: &str) -> String {
+    format!("{}.part", file)
+}
+
+/// 下载完成后返回需要修正扩展名时的新路径
+pub async fn download_file(task: &DownloadFile) -> Result<Option<String>> {
+    let part = part_path(&task.file);
+    if hls::is_hls(&task.url) {
+        hls::download_hls(&task.url, &part).await?;
+        // 改名只替换目录项，去重后的硬链接指向的另一份不受影响
+        tokio::fs::rename(&part, &task.file).await?;
+        debug!("完成 {:?}", &task);
+        return Ok(None);
+    }
+    let mut resp = timed(execute(client().get(&task.url)))
+        .await?
+        .error_for_status()?;
+    let total = resp.content_length().unwrap_or(0);
+    let content_type = header(&resp, CONTENT_TYPE);
+    let disposition = header(&resp, CONTENT_DISPOSITION);
+    let mut output = File::create(&part).await?;
+    let mut current = 0;
+    let mut head = Vec::new();
+    while let Some(chunk) = timed(resp.chunk()).await? {
+        if head.len() < MAGIC_LEN {
+            head.extend_from_slice(&chunk[..chunk.len().min(MAGIC_LEN - head.len())]);
+        }
+        output.write_all(&chunk).await?;
+        limit::throttle(chunk.len()).await;
+        current += chunk.len() as u64;
+        trace!("已下载:{}/{}", current, total);
+        update_state(task.id, |x| {
+            x.current = current;
+            x.total = total;
+        });
+    }
+    output.flush().await?;
+    drop(output);
+    tokio::fs::rename(&part, &task.file).await?;
     debug!("完成 {:?}", &task);
-    Ok(())
+    Ok(filetype::fix(
+        &task.file,
+        &content_type,
+        &disposition,
+        &head,
+    ))
 }
 
+/// 修正扩展名，新的名字已经被占用时保留原来的名字
+fn rename_task(task: &mut DownloadFile, path: String) {
+    if Path::new(&path).exists() {
+        warn!("{} 已存在，不修改 {} 的扩展名", path, task.file);
+        return;
+    }
+    match rename(&task.file, &path) {
+        Ok(_) => {
+            info!("修正扩展名 {} -> {}", task.file, path);
+            let old = std::mem::replace(&mut task.file, path);
+            task.original.get_or_insert(old);
+        }
+        Err(e) => warn!("修改文件名失败 {} {}", task.file, e),
+    }
+}
+
+/// 设置的同时下载数量，`debt` 为减少时还在正在下载的任务手里、归还时要收回的名额
+struct Concurrency {
+    target: usize,
+    debt: usize,
+}
+
+/// 调整同时下载的数量，减少时等正在下载的任务结束后生效
 pub fn set_num_threads(num_threads: usize) {
-    pool.lock().unwrap().set_num_threads(num_threads);
+    let num_threads = num_threads.max(1);
+    let mut lock = concurrency.lock().unwrap();
+    if num_threads > lock.target {
+        // 先抵消还没收回的名额
+        let add = num_threads - lock.target;
+        let cancel = add.min(lock.debt);
+        lock.debt -= cancel;
+        permits.add_permits(add - cancel);
+    } else if num_threads < lock.target {
+        let remove = lock.target - num_threads;
+        lock.debt += remove - permits.forget_permits(remove);
+    }
+    lock.target = num_threads;
+}
+
+/// 任务结束时归还名额，数量减少后还欠着的名额在这里收回
+fn release(permit: OwnedSemaphorePermit) {
+    let mut lock = concurrency.lock().unwrap();
+    if lock.debt > 0 {
+        lock.debt -= 1;
+        permit.forget();
+    }
 }
 
 pub fn retry_error_tasks() {
@@ -73,32 +371,119 @@ pub fn retry_error_tasks() {
         warn!("移动到任务队列：{:?}", &item);
         queue.push_back(item);
     }
+    wakeup.notify_one();
 }
 
-pub fn main() {
-    thread_manage::execute("下载主线程", move || {
-        let mutex_clone = Arc::clone(&download_queue);
-        let condvar_clone = Arc::new(&condvar);
-        let mut queue = mutex_clone.lock().unwrap();
-        loop {
-            match queue.pop_front() {
-                Some(task) => {
-                    info!("新建下载任务");
-                    pool.lock().unwrap().execute(move || {
-                        match download_file(&task) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                error_queue.lock().unwrap().push_back(task);
-                            }
-                        };
-                    });
-                }
+/// 取出下一个所在主机还有空闲连接的任务，并登记为正在下载
+fn start_next() -> Option<(DownloadFile, String, CancellationToken)> {
+    let mut queue = download_queue.lock().unwrap();
+    let index = queue
+        .iter()
+        .position(|x| limit::host_available(&limit::host(&x.url)))?;
+    let task = queue.remove(index)?;
+    // 记下占用的主机，下载期间切换 WebVPN 也能释放同一个
+    let host = limit::host(&task.url);
+    limit::acquire_host(&host);
+    let cancel = CancellationToken::new();
+    active_queue.lock().unwrap().push(TaskState {
+        task: task.clone(),
+        started: true,
+        current: 0,
+        total: 0,
+        cancel: cancel.clone(),
+    });
+    Some((task, host, cancel))
+}
+
+async fn dispatch() {
+    loop {
+        let permit = match permits.clone().acquire_owned().await {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        let (task, host, cancel) = loop {
+            match start_next() {
+                Some(v) => break v,
                 None => {
                     debug!("无任务");
-                    queue = condvar_clone.wait(queue).unwrap();
+                    wakeup.notified().await;
                     debug!("被唤醒");
                 }
+            }
+        };
+        info!("新建下载任务");
+        runtime().spawn(async move {
+            let mut task = task;
+            let ret = tokio::select! {
+                ret = download_file(&task) => ret,
+                _ = cancel.cancelled() => Err(anyhow!("已取消")),
             };
+            release(permit);
+            limit::release_host(&host);
+            // 同一主机的任务可能在等这个连接
+            wakeup.notify_one();
+            let cancelled = cancel.is_cancelled();
+            match &ret {
+                // 只删除不完整的临时文件，原来的文件保持不变
+                Err(_) => remove_file(part_path(&task.file)).unwrap_or_default(),
+                Ok(fixed) => {
+                    if let Some(path) = fixed {
+                        rename_task(&mut task, path.clone());
+                    }
+                    manifest::record(&task, task.action, task.original.clone());
+                    let (file, reference) = (task.file.clone(), task.reference.clone());
+                    let _ = tokio::task::spawn_blocking(move || {
+                        // 记录最终的位置，下次同一个 upload 可以直接链接
+                        if !reference.is_empty() {
+                            dedup::record_reference(&reference, &file);
+                        }
+                        dedup::after_download(&file);
+                    })
+                    .await;
+                }
+            }
+            match &ret {
+                Ok(_) => {}
+                Err(_) if cancelled => info!("已取消 {}", task.file),
+                Err(e) => {
+                    warn!("下载失败 {} {}", task.file, e);
+                    error_queue.lock().unwrap().push_back(task.clone());
+                }
+            };
+            // 先放入失败队列再移出，等待这个任务的地方不会错过失败的结果
+            active_queue
+                .lock()
+                .unwrap()
+                .retain(|x| x.task.id != task.id);
+            finish_task(&task, &ret, cancelled);
+        });
+    }
+}
+
+pub fn main() {
+    runtime().spawn(dispatch());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrency_follows_setting() {
+        let held = (0..2)
+            .map(|_| permits.clone().try_acquire_owned().unwrap())
+            .collect::<Vec<_>>();
+        // 正在下载的两个任务结束前只能先收回空闲的两个
+        set_num_threads(1);
+        assert_eq!(permits.available_permits(), 0);
+        // 还没收回的名额直接抵消
+        set_num_threads(3);
+        assert_eq!(permits.available_permits(), 1);
+        for permit in held {
+            release(permit);
         }
-    });
+        assert_eq!(permits.available_permits(), 3);
+        set_num_threads(DEFAULT_CONCURRENCY);
+        assert_eq!(permits.available_permits(), DEFAULT_CONCURRENCY);
+    }
 }
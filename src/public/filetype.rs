@@ -0,0 +1,207 @@
+use std::path::Path;
+
+/// 文件开头的魔数和对应的扩展名，第一个为默认
+const MAGIC: &[(&[u8], &[&str])] = &[
+    (b"%PDF", &["pdf"]),
+    (b"\x89PNG", &["png"]),
+    (b"\xff\xd8\xff", &["jpg", "jpeg"]),
+    (b"GIF8", &["gif"]),
+    (
+        b"PK\x03\x04",
+        &[
+            "zip", "docx", "pptx", "xlsx", "jar", "apk", "epub", "odt", "ods", "odp", "whl",
+        ],
+    ),
+    (b"Rar!", &["rar"]),
+    (b"7z\xbc\xaf\x27\x1c", &["7z"]),
+    (
+        b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+        &["doc", "xls", "ppt", "msi", "wps"],
+    ),
+    (b"\x1f\x8b", &["gz", "tgz"]),
+    (b"ID3", &["mp3"]),
+    (b"RIFF", &["wav", "avi", "webp"]),
+    (b"\x1a\x45\xdf\xa3", &["mkv", "webm"]),
+    (b"FLV", &["flv"]),
+];
+
+/// mp4 一类的文件在第 4 个字节后才是 `ftyp`
+const MP4: &[&str] = &["mp4", "m4a", "m4v", "mov"];
+
+/// 这些魔数是容器格式，上面只列了常见的扩展名，
+/// 不在列表中的（如 xmind、xlsm、WPS 的 et）也可能是正确的，已有扩展名时不修改
+const CONTAINERS: &[&str] = &["zip", "doc", "wav", "mkv", "gz", "mp4"];
+
+const CONTENT_TYPES: &[(&str, &str)] = &[
+    ("application/pdf", "pdf"),
+    ("application/msword", "doc"),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/zip", "zip"),
+    ("application/x-zip-compressed", "zip"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/x-7z-compressed", "7z"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("video/mp4", "mp4"),
+    ("audio/mpeg", "mp3"),
+    ("text/plain", "txt"),
+];
+
+fn by_magic(head: &[u8]) -> Option<&'static [&'static str]> {
+    if head.get(4..8) == Some(b"ftyp") {
+        return Some(MP4);
+    }
+    MAGIC
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, exts)| *exts)
+}
+
+fn by_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_lowercase();
+    CONTENT_TYPES
+        .iter()
+        .find(|(x, _)| *x == mime)
+        .map(|(_, ext)| *ext)
+}
+
+/// 课程中心的文件名中常有 `1.2节` 这样的点，只把短的字母数字后缀当作扩展名
+fn extension(name: &str) -> Option<String> {
+    let ext = Path::new(name).extension()?.to_str()?;
+    match (1..=5).contains(&ext.len()) && ext.chars().all(|x| x.is_ascii_alphanumeric()) {
+        true => Some(ext.to_lowercase()),
+        false => None,
+    }
+}
+
+/// 从 `filename*=UTF-8''a.pdf` 或 `filename="a.pdf"` 中取出扩展名
+fn by_disposition(disposition: &str) -> Option<String> {
+    let name = disposition
+        .split(';')
+        .map(|x| x.trim())
+        .filter_map(|x| x.split_once('='))
+        .filter(|(k, _)| k.trim().starts_with("filename"))
+        .map(|(_, v)| v.rsplit('\'').next().unwrap_or(v).trim_matches('"'))
+        .next_back()?;
+    extension(&urlencoding::decode(name).ok()?)
+}
+
+/// 根据响应头和文件开头的内容判断扩展名是否需要修改，返回修改后的路径。
+/// 已有扩展名时只在魔数能确定唯一的类型且不符、服务器给出的文件名也不是这个扩展名时才修改，
+/// 避免把源代码一类的文本文件改成 txt，或把容器格式的文件改成 zip
+pub fn fix(path: &str, content_type: &str, disposition: &str, head: &[u8]) -> Option<String> {
+    let magic = by_magic(head);
+    let named = by_disposition(disposition);
+    let current = extension(path);
+    if let Some(ext) = &current {
+        let keep = match magic {
+            None => true,
+            Some(exts) => {
+                exts.contains(&ext.as_str())
+                    || CONTAINERS.contains(&exts[0])
+                    || named.as_ref() == Some(ext)
+            }
+        };
+        if keep {
+            return None;
+        }
+    }
+    let header = named.or(by_content_type(content_type).map(String::from));
+    let ext = match magic {
+        Some(exts) => header
+            .filter(|x| exts.contains(&x.as_str()))
+            .unwrap_or(exts[0].to_string()),
+        None => header?,
+    };
+    let ret = match current {
+        Some(old) => format!("{}{}", &path[..path.len() - old.len()], ext),
+        None => format!("{}.{}", path, ext),
+    };
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PDF: &[u8] = b"%PDF-1.7\n";
+    const ZIP: &[u8] = b"PK\x03\x04\x14\x00";
+    const OLE: &[u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+
+    #[test]
+    fn appends_missing_extension() {
+        assert_eq!(fix("a/课件", "", "", PDF), Some("a/课件.pdf".to_string()));
+        assert_eq!(
+            fix(
+                "a/报告",
+                "",
+                "attachment; filename*=UTF-8''%E6%8A%A5.docx",
+                ZIP
+            ),
+            Some("a/报告.docx".to_string())
+        );
+        assert_eq!(fix("a/b", "", "", ZIP), Some("a/b.zip".to_string()));
+        assert_eq!(
+            fix("a/b", "text/plain; charset=utf-8", "", b"hello"),
+            Some("a/b.txt".to_string())
+        );
+        assert_eq!(fix("a/b", "", "", b"hello"), None);
+    }
+
+    #[test]
+    fn dotted_name_is_not_extension() {
+        assert_eq!(
+            fix("a/第1.2节", "", "", PDF),
+            Some("a/第1.2节.pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_matching_extension() {
+        assert_eq!(fix("a/b.PDF", "", "", PDF), None);
+        assert_eq!(fix("a/b.pptx", "application/zip", "", ZIP), None);
+        assert_eq!(fix("a/code.c", "text/plain", "", b"int main"), None);
+    }
+
+    #[test]
+    fn keeps_container_aliases() {
+        for name in ["a.xmind", "a.xlsm", "a.docm", "a.vsdx", "a.sb3"] {
+            assert_eq!(fix(name, "application/zip", "", ZIP), None, "{}", name);
+        }
+        for name in ["a.et", "a.dps"] {
+            assert_eq!(fix(name, "", "", OLE), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn keeps_extension_from_disposition() {
+        assert_eq!(
+            fix("a/b.ai", "", "attachment; filename=\"b.ai\"", PDF),
+            None
+        );
+    }
+
+    #[test]
+    fn replaces_wrong_extension() {
+        assert_eq!(fix("a/b.doc", "", "", PDF), Some("a/b.pdf".to_string()));
+        assert_eq!(
+            fix("a/b.jpg", "", "", b"\x89PNG\r\n"),
+            Some("a/b.png".to_string())
+        );
+    }
+}
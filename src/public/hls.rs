@@ -0,0 +1,252 @@
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use log::{debug, trace};
+use regex::Regex;
+use reqwest::Url;
+use soft_aes::aes::aes_dec_cbc;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+use crate::public::http::{block_on, client, execute, timed};
+use crate::public::limit;
+
+lazy_static! {
+    static ref REGEX_BANDWIDTH: Regex = Regex::new("BANDWIDTH=(\\d+)").unwrap();
+    static ref REGEX_RESOLUTION: Regex = Regex::new("RESOLUTION=(\\d+x\\d+)").unwrap();
+    static ref REGEX_KEY_URI: Regex = Regex::new("URI=\"([^\"]*)\"").unwrap();
+    static ref REGEX_KEY_IV: Regex = Regex::new("IV=0[xX]([0-9a-fA-F]{32})").unwrap();
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub bandwidth: u64,
+    pub resolution: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone)]
+struct Key {
+    url: String,
+    iv: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    url: String,
+    sequence: u64,
+    key: Option<Key>,
+}
+
+pub fn is_hls(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(u) => u.path().ends_with(".m3u8"),
+        Err(_) => false,
+    }
+}
+
+/// 在异步任务中获取一个地址的全部内容
+pub async fn get(url: &str) -> Result<Vec<u8>> {
+    let resp = timed(execute(client().get(url))).await?;
+    match resp.status().as_u16() {
+        200..=299 => Ok(timed(resp.bytes()).await?.to_vec()),
+        code => Err(anyhow!("请求 {} 返回状态码 {}", url, code)),
+    }
+}
+
+/// 同 `get`，供同步代码使用
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    block_on(get(url))
+}
+
+fn join(base: &str, url: &str) -> String {
+    match Url::parse(base).and_then(|x| x.join(url)) {
+        Ok(u) => u.to_string(),
+        Err(_) => url.to_string(),
+    }
+}
+
+fn parse_iv(hex: &str) -> Option<[u8; 16]> {
+    let mut iv = [0u8; 16];
+    for (i, byte) in iv.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(iv)
+}
+
+/// 解析多码率播放列表，普通播放列表返回空
+pub fn parse_variants(text: &str, base: &str) -> Vec<Variant> {
+    let mut variants = Vec::new();
+    let mut lines = text.lines().map(|x| x.trim());
+    while let Some(line) = lines.next() {
+        if !line.starts_with("#EXT-X-STREAM-INF") {
+            continue;
+        }
+        let bandwidth = REGEX_BANDWIDTH
+            .captures(line)
+            .and_then(|c| c[1].parse().ok())
+            .unwrap_or(0);
+        let resolution = REGEX_RESOLUTION
+            .captures(line)
+            .map(|c| c[1].to_string())
+            .unwrap_or_default();
+        if let Some(url) = lines.find(|x| !x.is_empty() && !x.starts_with('#')) {
+            variants.push(Variant {
+                bandwidth,
+                resolution,
+                url: join(base, url),
+            });
+        }
+    }
+    variants.sort_by_key(|x| std::cmp::Reverse(x.bandwidth));
+    variants
+}
+
+fn parse_segments(text: &str, base: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut sequence = 0;
+    let mut key = None;
+    for line in text.lines().map(|x| x.trim()) {
+        if let Some(v) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = v.parse().unwrap_or(0);
+        } else if line.starts_with("#EXT-X-KEY") {
+            key = match line.contains("METHOD=AES-128") {
+                true => REGEX_KEY_URI.captures(line).map(|c| Key {
+                    url: join(base, &c[1]),
+                    iv: REGEX_KEY_IV.captures(line).and_then(|c| parse_iv(&c[1])),
+                }),
+                false => None,
+            };
+        } else if !line.is_empty() && !line.starts_with('#') {
+            segments.push(Segment {
+                url: join(base, line),
+                sequence,
+                key: key.clone(),
+            });
+            sequence += 1;
+        }
+    }
+    segments
+}
+
+/// 下载 HLS 播放列表中的所有分片并按顺序拼接成一个 ts 文件
+pub async fn download_hls(url: &str, file: &str) -> Result<()> {
+    let mut url = url.to_string();
+    let mut text = String::from_utf8_lossy(&get(&url).await?).to_string();
+    if let Some(variant) = parse_variants(&text, &url).into_iter().next() {
+        debug!("选择码率 {:?}", variant);
+        url = variant.url;
+        text = String::from_utf8_lossy(&get(&url).await?).to_string();
+    }
+    let segments = parse_segments(&text, &url);
+    if segments.is_empty() {
+        return Err(anyhow!("播放列表中没有分片"));
+    }
+    let mut output = File::create(file).await?;
+    let mut key_cache: Option<(String, Vec<u8>)> = None;
+    for (i, segment) in segments.iter().enumerate() {
+        trace!("下载分片 {}/{} {}", i + 1, segments.len(), segment.url);
+        let mut data = get(&segment.url).await?;
+        limit::throttle(data.len()).await;
+        if let Some(key) = &segment.key {
+            let cached = key_cache.as_ref().filter(|(u, _)| *u == key.url);
+            let key_data = match cached {
+                Some((_, k)) => k.clone(),
+                None => {
+                    let k = get(&key.url).await?;
+                    key_cache = Some((key.url.clone(), k.clone()));
+                    k
+                }
+            };
+            let iv = key.iv.unwrap_or_else(|| {
+                let mut iv = [0u8; 16];
+                iv[8..].copy_from_slice(&segment.sequence.to_be_bytes());
+                iv
+            });
+            data = aes_dec_cbc(&data, &key_data, &iv, Some("PKCS7"))
+                .map_err(|e| anyhow!("分片解密失败 {}", e))?;
+        }
+        output.write_all(&data).await?;
+    }
+    output.flush().await?;
+    debug!("合并 {} 个分片到 {}", segments.len(), file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://video.example.com/live/a/index.m3u8";
+
+    #[test]
+    fn variants_sorted_by_bandwidth() {
+        let text = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\n\
+            360p/index.m3u8\n\
+            #EXT-X-STREAM-INF:PROGRAM-ID=1,BANDWIDTH=2500000,RESOLUTION=1920x1080\n\
+            \n\
+            https://cdn.example.com/1080p.m3u8\n";
+        let variants = parse_variants(text, BASE);
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].bandwidth, 2500000);
+        assert_eq!(variants[0].resolution, "1920x1080");
+        assert_eq!(variants[0].url, "https://cdn.example.com/1080p.m3u8");
+        assert_eq!(
+            variants[1].url,
+            "https://video.example.com/live/a/360p/index.m3u8"
+        );
+    }
+
+    #[test]
+    fn media_playlist_has_no_variants() {
+        let text = "#EXTM3U\n#EXTINF:10,\nseg0.ts\n";
+        assert!(parse_variants(text, BASE).is_empty());
+    }
+
+    #[test]
+    fn segments_with_sequence_and_keys() {
+        let text = "#EXTM3U\n\
+            #EXT-X-MEDIA-SEQUENCE:7\n\
+            #EXTINF:10,\n\
+            seg0.ts\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"/keys/k1\",IV=0x000102030405060708090a0b0c0d0e0f\n\
+            #EXTINF:10,\n\
+            seg1.ts\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"k2\"\n\
+            #EXTINF:10,\n\
+            seg2.ts\n\
+            #EXT-X-KEY:METHOD=NONE\n\
+            #EXTINF:10,\n\
+            seg3.ts\n\
+            #EXT-X-ENDLIST\n";
+        let segments = parse_segments(text, BASE);
+        assert_eq!(segments.len(), 4);
+        assert_eq!(
+            segments.iter().map(|x| x.sequence).collect::<Vec<_>>(),
+            vec![7, 8, 9, 10]
+        );
+        assert_eq!(segments[0].url, "https://video.example.com/live/a/seg0.ts");
+        assert!(segments[0].key.is_none());
+        let key = segments[1].key.as_ref().unwrap();
+        assert_eq!(key.url, "https://video.example.com/keys/k1");
+        assert_eq!(key.iv.unwrap()[15], 0x0f);
+        let key = segments[2].key.as_ref().unwrap();
+        assert_eq!(key.url, "https://video.example.com/live/a/k2");
+        assert!(key.iv.is_none());
+        assert!(segments[3].key.is_none());
+    }
+
+    #[test]
+    fn iv_needs_32_hex_digits() {
+        assert!(parse_iv("00").is_none());
+        assert!(parse_iv("zz0102030405060708090a0b0c0d0e0f").is_none());
+    }
+
+    #[test]
+    fn hls_urls() {
+        assert!(is_hls(BASE));
+        assert!(is_hls("https://a.com/x.m3u8?token=1"));
+        assert!(!is_hls("https://a.com/x.mp4"));
+        assert!(!is_hls("x.m3u8"));
+    }
+}
@@ -0,0 +1,54 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref REGEX_LINK: Regex =
+        Regex::new("(?is)<a[^>]*?href=\"([^\"]*)\"[^>]*>(.*?)</a>").unwrap();
+    static ref REGEX_BREAK: Regex = Regex::new("(?i)<br\\s*/?>|</(p|div|h\\d|tr)>").unwrap();
+    static ref REGEX_ITEM: Regex = Regex::new("(?i)<li[^>]*>").unwrap();
+    static ref REGEX_SKIP: Regex =
+        Regex::new("(?is)<(script|style)[^>]*>.*?</(script|style)>").unwrap();
+    static ref REGEX_TAG: Regex = Regex::new("(?s)<[^>]*>").unwrap();
+    static ref REGEX_ENTITY: Regex = Regex::new("&(#x?[0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    static ref REGEX_BLANK_LINES: Regex = Regex::new("\\n\\s*\\n(\\s*\\n)+").unwrap();
+}
+
+fn decode_entity(entity: &str) -> String {
+    if let Some(hex) = entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+        return u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+    }
+    if let Some(dec) = entity.strip_prefix('#') {
+        return dec
+            .parse()
+            .ok()
+            .and_then(char::from_u32)
+            .map(|x| x.to_string())
+            .unwrap_or_default();
+    }
+    match entity {
+        "nbsp" => " ",
+        "lt" => "<",
+        "gt" => ">",
+        "amp" => "&",
+        "quot" => "\"",
+        "apos" => "'",
+        _ => "",
+    }
+    .to_string()
+}
+
+/// 把 HTML 转成适合在终端阅读的纯文本，链接保留为 `文字 (地址)`
+pub fn to_text(html: &str) -> String {
+    let text = REGEX_SKIP.replace_all(html, "");
+    let text = REGEX_LINK.replace_all(&text, "$2 ($1)");
+    let text = REGEX_BREAK.replace_all(&text, "\n");
+    let text = REGEX_ITEM.replace_all(&text, "\n• ");
+    let text = REGEX_TAG.replace_all(&text, "");
+    let text = REGEX_ENTITY.replace_all(&text, |c: &regex::Captures| decode_entity(&c[1]));
+    let text = REGEX_BLANK_LINES.replace_all(&text, "\n\n");
+    text.trim().to_string()
+}
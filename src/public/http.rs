@@ -0,0 +1,165 @@
+use bytes::Bytes;
+use futures_util::stream;
+use lazy_static::lazy_static;
+use log::warn;
+use reqwest::{Body, Client, ClientBuilder, Proxy, Request, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::io::Read;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::public::webvpn;
+use crate::setting::config;
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
+
+const READ_CHUNK: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// 下载时等待响应或下一块数据的最长时间，连接卡住时放弃，
+/// 不然会一直占着下载名额和主机的连接数
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// 普通接口请求从连接到读完正文的最长时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref RUNTIME: Runtime = Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("网络线程")
+        .build()
+        .unwrap();
+    static ref CLIENT: RwLock<Client> = RwLock::new(builder().build().unwrap());
+}
+
+/// 所有请求共用的设置，需要单独 cookie 的登录会话也从这里开始
+pub fn builder() -> ClientBuilder {
+    let builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .connect_timeout(CONNECT_TIMEOUT);
+    let proxy = config::get().proxy;
+    if proxy.is_empty() {
+        return builder;
+    }
+    match Proxy::all(&proxy) {
+        Ok(v) => builder.proxy(v),
+        Err(e) => {
+            warn!("代理地址无效 {} {}", proxy, e);
+            builder
+        }
+    }
+}
+
+/// 共享的客户端，克隆只增加引用计数，连接池是同一个
+pub fn client() -> Client {
+    CLIENT.read().unwrap().clone()
+}
+
+/// 修改代理后重新创建共享的客户端
+pub fn reload() {
+    *CLIENT.write().unwrap() = builder().build().unwrap();
+}
+
+pub fn runtime() -> &'static Runtime {
+    &RUNTIME
+}
+
+/// 在同步代码中等待异步任务，不能在运行时内部调用
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+/// 已经读完正文的响应，供同步代码使用
+pub struct Response {
+    status: StatusCode,
+    url: Url,
+    body: Bytes,
+}
+
+impl Response {
+    pub async fn read(resp: reqwest::Response) -> reqwest::Result<Self> {
+        Ok(Self {
+            status: resp.status(),
+            url: webvpn::restore(resp.url()),
+            body: resp.bytes().await?,
+        })
+    }
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+/// 按 WebVPN 改写地址，`cookie` 为 false 时由客户端自己的 cookie 保存门户凭据
+fn prepare(request: RequestBuilder, cookie: bool) -> reqwest::Result<(Client, Request)> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    if webvpn::rewrite(request.url_mut()) && cookie {
+        webvpn::add_cookie(request.headers_mut());
+    }
+    Ok((client, request))
+}
+
+/// 所有请求都从这里发出，保证代理和 WebVPN 的设置一致
+pub async fn execute(request: RequestBuilder) -> reqwest::Result<reqwest::Response> {
+    let (client, request) = prepare(request, true)?;
+    client.execute(request).await
+}
+
+/// 下载中等待响应或数据时使用，超过 `READ_TIMEOUT` 没有进展就返回错误。
+/// 上传时服务器要收完才响应，所以没有设置在客户端上
+pub async fn timed<T>(future: impl Future<Output = reqwest::Result<T>>) -> anyhow::Result<T> {
+    match tokio::time::timeout(READ_TIMEOUT, future).await {
+        Ok(ret) => Ok(ret?),
+        Err(_) => Err(anyhow::anyhow!(
+            "{} 秒内没有收到数据",
+            READ_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// 发送请求并读完正文，超过 `REQUEST_TIMEOUT` 返回错误
+pub fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+    send_upload(request.timeout(REQUEST_TIMEOUT))
+}
+
+/// 上传使用，服务器要收完文件才响应，时间不定，只有连接超时
+pub fn send_upload(request: RequestBuilder) -> reqwest::Result<Response> {
+    block_on(async { Response::read(execute(request).await?).await })
+}
+
+/// 登录会话使用，门户凭据已经在会话的 cookie 中
+pub fn send_in_session(request: RequestBuilder) -> reqwest::Result<Response> {
+    block_on(async {
+        let (client, request) = prepare(request.timeout(REQUEST_TIMEOUT), false)?;
+        Response::read(client.execute(request).await?).await
+    })
+}
+
+/// 把同步的 `Read` 包装成请求体，上传时边读边发
+pub fn reader_body<R: Read + Send + 'static>(reader: R) -> Body {
+    let stream = stream::unfold(reader, |mut reader| async move {
+        let mut buf = vec![0; READ_CHUNK];
+        match reader.read(&mut buf) {
+            Ok(0) => None,
+            Ok(len) => {
+                buf.truncate(len);
+                Some((Ok::<_, std::io::Error>(buf), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    });
+    Body::wrap_stream(stream)
+}
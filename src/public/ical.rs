@@ -0,0 +1,150 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use log::debug;
+use std::fs::File;
+use std::io::Write;
+
+use crate::public::storage::create_parent;
+
+/// RFC 5545 规定每行不超过 75 个字节（不含换行）
+const LINE_LIMIT: usize = 75;
+
+/// 厦门大学所有时间都按北京时间计算
+pub fn beijing() -> FixedOffset {
+    FixedOffset::east_opt(8 * 3600).unwrap()
+}
+
+pub fn now() -> NaiveDateTime {
+    Local::now().with_timezone(&beijing()).naive_local()
+}
+
+/// 解析接口返回的 RFC 3339 时间并转换为北京时间
+pub fn parse_rfc3339(time: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.with_timezone(&beijing()).naive_local())
+}
+
+pub fn countdown(time: &NaiveDateTime) -> String {
+    let duration = *time - now();
+    if duration.num_seconds() <= 0 {
+        return "已结束".to_string();
+    }
+    match duration.num_days() {
+        0 => format!(
+            "还有 {} 小时 {} 分钟",
+            duration.num_hours(),
+            duration.num_minutes() % 60
+        ),
+        days => format!("还有 {} 天 {} 小时", days, duration.num_hours() % 24),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub location: String,
+    pub description: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// 长行折成多行，后续行以空格开头，不拆开 UTF-8 字符
+fn fold_line(line: &str) -> String {
+    let mut ret = String::with_capacity(line.len() + line.len() / LINE_LIMIT * 3);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > LINE_LIMIT {
+            ret.push_str("\r\n ");
+            // 开头的空格也算在这一行的长度中
+            len = 1;
+        }
+        ret.push(c);
+        len += c.len_utf8();
+    }
+    ret
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    match beijing().from_local_datetime(time).single() {
+        Some(t) => t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string(),
+        None => time.format("%Y%m%dT%H%M%S").to_string(),
+    }
+}
+
+pub fn write_calendar(path: &str, name: &str, events: &[Event]) -> Result<()> {
+    create_parent(path)?;
+    let now = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//xmu_assistant//CN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@xmu_assistant", event.uid));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("DTSTART:{}", format_time(&event.start)));
+        lines.push(format!("DTEND:{}", format_time(&event.end)));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if !event.location.is_empty() {
+            lines.push(format!("LOCATION:{}", escape_text(&event.location)));
+        }
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    let mut file = File::create(path)?;
+    let lines = lines.iter().map(|x| fold_line(x)).collect::<Vec<_>>();
+    file.write_all((lines.join("\r\n") + "\r\n").as_bytes())?;
+    debug!("写入日历文件 {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(escape_text("a,b;c\\d"), "a\\,b\\;c\\\\d");
+        assert_eq!(
+            escape_text("第一行\r\n第二行\n第三行"),
+            "第一行\\n第二行\\n第三行"
+        );
+    }
+
+    #[test]
+    fn short_lines_unchanged() {
+        let line = "SUMMARY:高等数学";
+        assert_eq!(fold_line(line), line);
+        let line = "X".repeat(LINE_LIMIT);
+        assert_eq!(fold_line(&line), line);
+    }
+
+    #[test]
+    fn folds_at_75_octets() {
+        let line = format!("SUMMARY:{}", "计算机网络与通信原理".repeat(5));
+        let folded = fold_line(&line);
+        for part in folded.split("\r\n") {
+            assert!(part.len() <= LINE_LIMIT, "{} 字节", part.len());
+        }
+        let parts = folded.split("\r\n").collect::<Vec<_>>();
+        assert!(parts.len() > 1);
+        assert!(parts[1..].iter().all(|x| x.starts_with(' ')));
+        let unfolded = folded.replace("\r\n ", "");
+        assert_eq!(unfolded, line);
+    }
+}
@@ -0,0 +1,93 @@
+use chrono::Local;
+use lazy_static::lazy_static;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::public::webvpn;
+use crate::setting::config;
+
+lazy_static! {
+    /// 限速时下一段数据最早可以写入的时间，所有下载共用
+    static ref next_slot: Mutex<Option<Instant>> = Mutex::new(None);
+    /// 每个主机正在下载的数量
+    static ref hosts: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+}
+
+/// 当前时段生效的限速（字节/秒），0 为不限
+pub fn current_limit() -> u64 {
+    let config = config::get();
+    let time = Local::now().time();
+    let kb = config
+        .speed_schedules
+        .iter()
+        .find(|x| x.contains(time))
+        .map(|x| x.limit_kb)
+        .unwrap_or(config.speed_limit_kb);
+    kb * 1024
+}
+
+/// 按限速等待，读到 `len` 字节后调用
+pub async fn throttle(len: usize) {
+    let limit = current_limit();
+    if limit == 0 {
+        return;
+    }
+    let wait = {
+        let mut lock = next_slot.lock().unwrap();
+        let now = Instant::now();
+        let start = lock.map(|x| x.max(now)).unwrap_or(now);
+        *lock = Some(start + Duration::from_secs_f64(len as f64 / limit as f64));
+        start - now
+    };
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// 请求实际发往的主机，开启 WebVPN 时校内的地址都经过门户
+pub fn host(url: &str) -> String {
+    let mut url = match Url::parse(url) {
+        Ok(v) => v,
+        Err(_) => return String::new(),
+    };
+    webvpn::rewrite(&mut url);
+    url.host_str().unwrap_or_default().to_string()
+}
+
+/// 这个主机还能不能再开始一个下载
+pub fn host_available(host: &str) -> bool {
+    match config::get().per_host_limit {
+        0 => true,
+        limit => hosts.lock().unwrap().get(host).copied().unwrap_or(0) < limit,
+    }
+}
+
+pub fn acquire_host(host: &str) {
+    *hosts.lock().unwrap().entry(host.to_string()).or_default() += 1;
+}
+
+pub fn release_host(host: &str) {
+    let mut lock = hosts.lock().unwrap();
+    if let Some(count) = lock.get_mut(host) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            lock.remove(host);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_of_url() {
+        assert_eq!(
+            host("https://cdn.example.com:8443/a.pdf?x=1"),
+            "cdn.example.com"
+        );
+        assert_eq!(host("not a url"), "");
+    }
+}
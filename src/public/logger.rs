@@ -1,11 +1,65 @@
 use ansi_term::Colour;
 use chrono::Local;
-use env_logger::Builder;
+use env_logger::{Builder, Target};
+use lazy_static::lazy_static;
 use log::{Level, LevelFilter};
-use std::io::Write;
+use std::collections::VecDeque;
+use std::io::{stderr, Write};
+use std::sync::Mutex;
 use std::thread;
 
 pub const LEVEL: LevelFilter = LevelFilter::Info;
+const CAPTURE_LINES: usize = 500;
+
+lazy_static! {
+    static ref CAPTURE: Mutex<Option<VecDeque<String>>> = Mutex::new(None);
+}
+
+/// 全屏界面运行时日志写入内存，否则写到 stderr
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut lock = CAPTURE.lock().unwrap();
+        match lock.as_mut() {
+            Some(lines) => {
+                for line in String::from_utf8_lossy(buf).lines() {
+                    lines.push_back(line.to_string());
+                    if lines.len() > CAPTURE_LINES {
+                        lines.pop_front();
+                    }
+                }
+            }
+            None => stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        stderr().flush()
+    }
+}
+
+pub fn start_capture() {
+    let mut lock = CAPTURE.lock().unwrap();
+    if lock.is_none() {
+        *lock = Some(VecDeque::new());
+    }
+}
+
+pub fn stop_capture() {
+    *CAPTURE.lock().unwrap() = None;
+}
+
+fn is_capturing() -> bool {
+    CAPTURE.lock().unwrap().is_some()
+}
+
+pub fn captured() -> Vec<String> {
+    match CAPTURE.lock().unwrap().as_ref() {
+        Some(lines) => lines.iter().cloned().collect(),
+        None => Vec::new(),
+    }
+}
 
 pub fn main() {
     let main_id = thread::current().id();
@@ -17,6 +71,16 @@ pub fn main() {
             } else {
                 "主线程"
             };
+            if is_capturing() {
+                return writeln!(
+                    buf,
+                    "[{}][{}][{}] {}",
+                    Local::now().format("%H:%M:%S"),
+                    thread_name,
+                    record.level(),
+                    record.args(),
+                );
+            }
             writeln!(
                 buf,
                 "[{}][{}][{}][{}][{}] {}",
@@ -35,6 +99,7 @@ pub fn main() {
             )
         })
         .filter(None, LEVEL)
+        .target(Target::Pipe(Box::new(LogWriter)))
         .init();
 }
 
@@ -51,6 +116,9 @@ impl LoggerData {
             data: data.to_string(),
         }
     }
+    pub fn message(&self) -> &str {
+        &self.data
+    }
     pub fn logger(&self) {
         match self.level {
             LevelFilter::Debug => log::debug!("{}", &self.data),
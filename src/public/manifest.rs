@@ -0,0 +1,383 @@
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::public::download_file::DownloadFile;
+use crate::public::ical::now;
+use crate::public::storage::{read_json, write_json, DATA_PATH};
+use crate::setting::config::{self, ConflictPolicy};
+
+lazy_static! {
+    static ref MANIFEST: Manifest = Manifest::open(&format!("{}manifest.json", DATA_PATH));
+}
+
+/// 保存到这个位置的原因
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Action {
+    #[default]
+    New,
+    Overwrite,
+    Rename,
+    KeepBoth,
+}
+
+/// 下载过的文件，键为保存的位置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub reference: String,
+    pub url: String,
+    pub size: u64,
+    pub downloaded_at: Option<NaiveDateTime>,
+    pub action: Action,
+    /// 因为冲突或修正扩展名改名前的文件名
+    pub original: Option<String>,
+}
+
+/// 下载清单和保存的位置，程序中只用 `data/manifest.json` 这一份
+struct Manifest {
+    path: String,
+    entries: Mutex<BTreeMap<String, Entry>>,
+}
+
+impl Manifest {
+    fn open(path: &str) -> Self {
+        let entries = read_json(path)
+            .and_then(|x| serde_json::from_value(x).ok())
+            .unwrap_or_default();
+        Self {
+            path: path.to_string(),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn save(&self, entries: &BTreeMap<String, Entry>) {
+        let ret = serde_json::to_value(entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|x| write_json(&self.path, &x));
+        if let Err(e) = ret {
+            warn!("保存下载清单失败 {}", e);
+        }
+    }
+
+    fn record(&self, task: &DownloadFile, action: Action, original: Option<String>) {
+        let size = fs::metadata(&task.file).map(|x| x.len()).unwrap_or(0);
+        let mut lock = self.entries.lock().unwrap();
+        lock.insert(
+            task.file.clone(),
+            Entry {
+                reference: task.reference.clone(),
+                url: task.url.clone(),
+                size,
+                downloaded_at: Some(now()),
+                action,
+                original,
+            },
+        );
+        self.save(&lock);
+    }
+
+    /// 本地已有的文件是否就是要下载的内容：清单中有记录时比较 reference_id，
+    /// 否则只能比较大小
+    fn same_as_local(&self, task: &DownloadFile, path: &str) -> bool {
+        let len = match fs::metadata(path) {
+            Ok(v) => v.len(),
+            Err(_) => return false,
+        };
+        let size_ok = task.size == 0 || task.size == len;
+        match self.entries.lock().unwrap().get(path) {
+            Some(entry) if !task.reference.is_empty() => {
+                entry.reference == task.reference && size_ok
+            }
+            _ => task.size != 0 && size_ok,
+        }
+    }
+
+    /// 之前改名保存过的同一个文件，`size` 为 0 时不比较大小
+    fn renamed(&self, reference: &str, path: &str, size: u64) -> Option<String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, e)| e.original.as_deref() == Some(path) && e.reference == reference)
+            .filter(|(_, e)| size == 0 || e.size == size)
+            .map(|(p, _)| p.clone())
+            .find(|p| Path::new(p).exists())
+    }
+
+    fn renamed_copy(&self, task: &DownloadFile, path: &str) -> Option<String> {
+        match task.reference.is_empty() {
+            true => None,
+            false => self.renamed(&task.reference, path, task.size),
+        }
+    }
+
+    fn locate(&self, reference: &str, requested: &str) -> Option<String> {
+        if let Some(path) = self.renamed(reference, requested, 0) {
+            return Some(path);
+        }
+        let other = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(requested)
+            .is_some_and(|e| e.reference != reference);
+        match !other && Path::new(requested).exists() {
+            true => Some(requested.to_string()),
+            false => None,
+        }
+    }
+
+    fn resolve(
+        &self,
+        task: &DownloadFile,
+        pending: &[DownloadFile],
+        policy: ConflictPolicy,
+    ) -> Option<(String, Action)> {
+        let path = task.file.as_str();
+        let queued = pending.iter().find(|x| x.file == path);
+        // 同步时覆盖相同的文件只会每次都重新下载
+        let overwrite = policy == ConflictPolicy::Overwrite && !task.is_sync();
+        // 改过名的文件（冲突或修正扩展名）按原来的名字也能找到
+        if let (false, Some(copy)) = (overwrite, self.renamed_copy(task, path)) {
+            info!("已经保存为 {}，跳过", copy);
+            return None;
+        }
+        let exists = Path::new(path).exists();
+        if queued.is_none() && !exists {
+            return Some((path.to_string(), Action::New));
+        }
+        let identical = match queued {
+            Some(other) => same_task(task, other),
+            None => self.same_as_local(task, path),
+        };
+        // 同一个任务已经在队列中时覆盖也没有意义
+        if identical && (queued.is_some() || !overwrite) {
+            info!("已存在相同的文件，跳过 {}", path);
+            return None;
+        }
+        let taken = |p: &str| Path::new(p).exists() || pending.iter().any(|x| x.file == p);
+        let numbered = |base: &str| {
+            (1..)
+                .map(|i| with_suffix(base, &format!(" ({})", i)))
+                .find(|x| !taken(x))
+                .unwrap()
+        };
+        let ret = match policy {
+            ConflictPolicy::SkipIdentical | ConflictPolicy::Overwrite => {
+                // 同名的文件正在下载时不能覆盖，只能改名
+                match queued {
+                    Some(_) => (numbered(path), Action::Rename),
+                    None => (path.to_string(), Action::Overwrite),
+                }
+            }
+            ConflictPolicy::Rename => (numbered(path), Action::Rename),
+            ConflictPolicy::KeepBoth => {
+                let reference = match task.reference.is_empty() {
+                    true => "new",
+                    false => task.reference.as_str(),
+                };
+                let base = with_suffix(path, &format!("_{}", reference));
+                match taken(&base) {
+                    true => (numbered(&base), Action::KeepBoth),
+                    false => (base, Action::KeepBoth),
+                }
+            }
+        };
+        match ret.0 == path {
+            true => info!("{} 已存在，下载新的版本覆盖", path),
+            false => info!("{} 已存在，保存为 {}", path, ret.0),
+        }
+        Some(ret)
+    }
+}
+
+/// 下载成功后记录
+pub fn record(task: &DownloadFile, action: Action, original: Option<String>) {
+    MANIFEST.record(task, action, original)
+}
+
+/// 本来要保存到 `requested` 的文件现在实际所在的位置，没有下载时返回 None
+pub fn locate(reference: &str, requested: &str) -> Option<String> {
+    MANIFEST.locate(reference, requested)
+}
+
+fn same_task(a: &DownloadFile, b: &DownloadFile) -> bool {
+    a.url == b.url || (!a.reference.is_empty() && a.reference == b.reference)
+}
+
+/// 在扩展名前插入后缀
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let p = Path::new(path);
+    let stem = p
+        .file_stem()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default();
+    let name = match p.extension() {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    match p.parent() {
+        Some(parent) => parent.join(name).to_string_lossy().to_string(),
+        None => name,
+    }
+}
+
+/// 按设置处理文件名冲突，返回 None 表示不用下载；
+/// `pending` 为已经在队列中或正在下载的任务
+pub fn resolve(task: &DownloadFile, pending: &[DownloadFile]) -> Option<(String, Action)> {
+    MANIFEST.resolve(task, pending, config::get().conflict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试用的目录和其中单独的清单，结束时删除
+    struct TempDir {
+        path: String,
+        manifest: Manifest,
+    }
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("xmu_manifest_{}_{}", name, std::process::id()));
+            fs::remove_dir_all(&dir).unwrap_or_default();
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.to_string_lossy().to_string();
+            let manifest = Manifest::open(&format!("{}/manifest.json", path));
+            Self { path, manifest }
+        }
+        fn file(&self, name: &str) -> String {
+            format!("{}/{}", self.path, name)
+        }
+        fn resolve(
+            &self,
+            task: &DownloadFile,
+            pending: &[DownloadFile],
+            policy: ConflictPolicy,
+        ) -> Option<(String, Action)> {
+            self.manifest.resolve(task, pending, policy)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.path).unwrap_or_default();
+        }
+    }
+
+    fn task(file: &str, reference: &str, size: u64) -> DownloadFile {
+        DownloadFile::new("https://example.com/a", file).with_reference(reference, size)
+    }
+
+    #[test]
+    fn suffix_before_extension() {
+        assert_eq!(with_suffix("a/b.pdf", " (1)"), "a/b (1).pdf");
+        assert_eq!(with_suffix("a/b", "_2"), "a/b_2");
+        assert_eq!(with_suffix("b.tar.gz", " (1)"), "b.tar (1).gz");
+    }
+
+    #[test]
+    fn new_file_is_downloaded() {
+        let dir = TempDir::new("new");
+        let file = dir.file("a.pdf");
+        let ret = dir.resolve(&task(&file, "1", 3), &[], ConflictPolicy::SkipIdentical);
+        assert_eq!(ret, Some((file, Action::New)));
+    }
+
+    #[test]
+    fn identical_local_file() {
+        let dir = TempDir::new("identical");
+        let file = dir.file("a.pdf");
+        fs::write(&file, "abc").unwrap();
+        let t = task(&file, "1", 3);
+        assert_eq!(dir.resolve(&t, &[], ConflictPolicy::SkipIdentical), None);
+        assert_eq!(dir.resolve(&t, &[], ConflictPolicy::Rename), None);
+        assert_eq!(
+            dir.resolve(&t, &[], ConflictPolicy::Overwrite),
+            Some((file.clone(), Action::Overwrite))
+        );
+        // 同步时即使设置为覆盖也跳过
+        assert_eq!(
+            dir.resolve(&t.for_sync(), &[], ConflictPolicy::Overwrite),
+            None
+        );
+    }
+
+    #[test]
+    fn different_local_file() {
+        let dir = TempDir::new("different");
+        let file = dir.file("a.pdf");
+        fs::write(&file, "abc").unwrap();
+        fs::write(dir.file("a (1).pdf"), "abc").unwrap();
+        let t = task(&file, "7", 5);
+        assert_eq!(
+            dir.resolve(&t, &[], ConflictPolicy::SkipIdentical),
+            Some((file.clone(), Action::Overwrite))
+        );
+        assert_eq!(
+            dir.resolve(&t, &[], ConflictPolicy::Rename),
+            Some((dir.file("a (2).pdf"), Action::Rename))
+        );
+        assert_eq!(
+            dir.resolve(&t, &[], ConflictPolicy::KeepBoth),
+            Some((dir.file("a_7.pdf"), Action::KeepBoth))
+        );
+    }
+
+    #[test]
+    fn queued_file() {
+        let dir = TempDir::new("queued");
+        let file = dir.file("a.pdf");
+        let same = task(&file, "1", 3);
+        let pending = [same.clone()];
+        assert_eq!(
+            dir.resolve(&same, &pending, ConflictPolicy::Overwrite),
+            None
+        );
+        let mut other = task(&file, "2", 3);
+        other.url = "https://example.com/b".to_string();
+        assert_eq!(
+            dir.resolve(&other, &[same], ConflictPolicy::Overwrite),
+            Some((dir.file("a (1).pdf"), Action::Rename))
+        );
+    }
+
+    #[test]
+    fn recorded_files() {
+        let dir = TempDir::new("recorded");
+        let file = dir.file("a.pdf");
+        let renamed = dir.file("a (1).pdf");
+        fs::write(&file, "abc").unwrap();
+        fs::write(&renamed, "abcd").unwrap();
+        dir.manifest.record(&task(&file, "1", 3), Action::New, None);
+        dir.manifest
+            .record(&task(&renamed, "2", 4), Action::Rename, Some(file.clone()));
+        // 清单中记录的 reference_id 不同时不算相同的文件
+        assert_eq!(
+            dir.resolve(&task(&file, "3", 3), &[], ConflictPolicy::SkipIdentical),
+            Some((file.clone(), Action::Overwrite))
+        );
+        // 改名保存过的按原来的名字也能找到
+        assert_eq!(
+            dir.resolve(&task(&file, "2", 4), &[], ConflictPolicy::Rename),
+            None
+        );
+        assert_eq!(dir.manifest.locate("2", &file), Some(renamed));
+        assert_eq!(dir.manifest.locate("1", &file), Some(file.clone()));
+        assert_eq!(dir.manifest.locate("3", &file), None);
+        // 写入的是测试自己的清单
+        assert!(Manifest::open(&dir.file("manifest.json"))
+            .entries
+            .lock()
+            .unwrap()
+            .contains_key(&file));
+    }
+}
@@ -1,7 +1,19 @@
 pub mod download_file;
 pub use download_file::DownloadFile;
+pub mod dedup;
+pub mod filetype;
+pub mod hls;
+pub mod html;
+pub mod http;
+pub mod ical;
+pub mod limit;
 pub mod logger;
+pub mod manifest;
+pub mod notify;
+pub mod progress;
+pub mod storage;
 pub mod thread_manage;
+pub mod webvpn;
 
 pub fn main() {
     logger::main();
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use log::{debug, info, warn};
+use serde_json::json;
+use std::thread;
+
+use crate::public::http::{client, send};
+use crate::setting::config::{self, SmtpConfig};
+
+/// 列表中最多写出多少个文件
+const MAX_LISTED: usize = 20;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    NewFiles { course: String, files: Vec<String> },
+    DownloadsCompleted { count: usize },
+    TasksFailed { files: Vec<String> },
+    SessionExpired { profile: String },
+    Test,
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::NewFiles { .. } => "new_files",
+            Event::DownloadsCompleted { .. } => "downloads_completed",
+            Event::TasksFailed { .. } => "tasks_failed",
+            Event::SessionExpired { .. } => "session_expired",
+            Event::Test => "test",
+        }
+    }
+    fn title(&self) -> String {
+        match self {
+            Event::NewFiles { course, files } => {
+                format!("{} 新增 {} 个文件", course, files.len())
+            }
+            Event::DownloadsCompleted { count } => format!("{} 个文件下载完成", count),
+            Event::TasksFailed { files } => format!("{} 个下载任务失败", files.len()),
+            Event::SessionExpired { profile } => format!("账号 {} 登录已失效", profile),
+            Event::Test => "测试通知".to_string(),
+        }
+    }
+    fn files(&self) -> &[String] {
+        match self {
+            Event::NewFiles { files, .. } | Event::TasksFailed { files } => files,
+            _ => &[],
+        }
+    }
+    fn body(&self) -> String {
+        let files = self.files();
+        let mut lines = files.iter().take(MAX_LISTED).cloned().collect::<Vec<_>>();
+        if files.len() > MAX_LISTED {
+            lines.push(format!("等 {} 个文件", files.len()));
+        }
+        if let Event::SessionExpired { .. } = self {
+            lines.push("请重新登录，或在账号中保存密码以便自动登录".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+trait Notifier {
+    fn name(&self) -> &'static str;
+    fn send(&self, event: &Event) -> Result<()>;
+}
+
+/// 通过 D-Bus 发送桌面通知
+struct Desktop;
+
+impl Notifier for Desktop {
+    fn name(&self) -> &'static str {
+        "桌面通知"
+    }
+    fn send(&self, event: &Event) -> Result<()> {
+        notify_rust::Notification::new()
+            .appname("xmu_assistant")
+            .summary(&event.title())
+            .body(&event.body())
+            .show()?;
+        Ok(())
+    }
+}
+
+/// 以 JSON 向任意地址 POST
+struct Webhook {
+    url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "Webhook"
+    }
+    fn send(&self, event: &Event) -> Result<()> {
+        let resp = send(client().post(&self.url).json(&json!({
+            "event": event.kind(),
+            "title": event.title(),
+            "body": event.body(),
+            "files": event.files(),
+        })))?;
+        match resp.status().is_success() {
+            true => Ok(()),
+            false => Err(anyhow!("返回状态码 {}", resp.status())),
+        }
+    }
+}
+
+struct Email {
+    config: SmtpConfig,
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "邮件"
+    }
+    fn send(&self, event: &Event) -> Result<()> {
+        let message = Message::builder()
+            .from(self.config.from.parse()?)
+            .to(self.config.to.parse()?)
+            .subject(event.title())
+            .header(ContentType::TEXT_PLAIN)
+            .body(event.body())?;
+        let mut transport = SmtpTransport::relay(&self.config.server)?;
+        if self.config.port != 0 {
+            transport = transport.port(self.config.port);
+        }
+        if !self.config.username.is_empty() {
+            transport = transport.credentials(Credentials::new(
+                self.config.username.clone(),
+                self.config.password.clone(),
+            ));
+        }
+        transport.build().send(&message)?;
+        Ok(())
+    }
+}
+
+fn notifiers() -> Vec<Box<dyn Notifier + Send>> {
+    let config = config::get().notify;
+    let mut ret: Vec<Box<dyn Notifier + Send>> = Vec::new();
+    if config.desktop {
+        ret.push(Box::new(Desktop));
+    }
+    if !config.webhook_url.is_empty() {
+        ret.push(Box::new(Webhook {
+            url: config.webhook_url,
+        }));
+    }
+    if let Some(smtp) = config.smtp.filter(|x| !x.server.is_empty()) {
+        ret.push(Box::new(Email { config: smtp }));
+    }
+    ret
+}
+
+/// 在后台把事件发给所有启用的通知方式
+pub fn notify(event: Event) {
+    let notifiers = notifiers();
+    if notifiers.is_empty() {
+        return;
+    }
+    thread::spawn(move || {
+        for notifier in notifiers {
+            match notifier.send(&event) {
+                Ok(_) => debug!("已通过{}发送 {}", notifier.name(), event.kind()),
+                Err(e) => warn!("{}发送失败 {}", notifier.name(), e),
+            }
+        }
+    });
+}
+
+/// 同步发送一条测试通知，逐个报告结果
+pub fn send_test() {
+    let notifiers = notifiers();
+    if notifiers.is_empty() {
+        warn!("没有启用任何通知方式");
+    }
+    for notifier in notifiers {
+        match notifier.send(&Event::Test) {
+            Ok(_) => info!("{}发送成功", notifier.name()),
+            Err(e) => warn!("{}发送失败 {}", notifier.name(), e),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::io::{stdout, Read, Write};
+
+const BAR_WIDTH: usize = 30;
+
+pub fn render_bar(current: u64, total: u64) -> String {
+    let ratio = match total {
+        0 => 1.0,
+        total => (current as f64 / total as f64).min(1.0),
+    };
+    let filled = (ratio * BAR_WIDTH as f64) as usize;
+    format!(
+        "[{}{}] {:>5.1}% {}/{}",
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        ratio * 100.0,
+        format_size(current),
+        format_size(total)
+    )
+}
+
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// 读取时在终端同一行刷新进度条
+pub struct ProgressReader<R: Read> {
+    inner: R,
+    current: u64,
+    total: u64,
+    done: bool,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total: u64) -> Self {
+        Self {
+            inner,
+            current: 0,
+            total,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if self.done {
+            return Ok(len);
+        }
+        self.current += len as u64;
+        print!("\r{}", render_bar(self.current, self.total));
+        if len == 0 || self.current >= self.total {
+            self.done = true;
+            println!();
+        }
+        stdout().flush()?;
+        Ok(len)
+    }
+}
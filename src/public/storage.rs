@@ -0,0 +1,91 @@
+use anyhow::Result;
+use log::debug;
+use serde_json::Value;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+pub const DATA_PATH: &str = "./data/";
+pub const EXPORT_PATH: &str = "./export/";
+
+pub fn create_parent(path: &str) -> Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+/// 把文件名中不能使用的字符换成 `_`
+pub fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn read_json(path: &str) -> Option<Value> {
+    let file = File::open(path).ok()?;
+    serde_json::from_reader(BufReader::new(file)).ok()
+}
+
+pub fn write_json(path: &str, value: &Value) -> Result<()> {
+    create_parent(path)?;
+    let mut file = File::create(path)?;
+    file.write_all(serde_json::to_string_pretty(value)?.as_bytes())?;
+    debug!("写入 json 文件 {}", path);
+    Ok(())
+}
+
+/// 在文本文件末尾追加内容
+pub fn append_text(path: &str, text: &str) -> Result<()> {
+    create_parent(path)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn write_csv(path: &str, headers: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    create_parent(path)?;
+    let mut file = File::create(path)?;
+    // 写入 BOM 以便 Excel 正确识别 UTF-8
+    file.write_all("\u{feff}".as_bytes())?;
+    writeln!(file, "{}", headers.join(","))?;
+    for row in rows {
+        let line = row.iter().map(|x| escape_csv(x)).collect::<Vec<_>>();
+        writeln!(file, "{}", line.join(","))?;
+    }
+    debug!("写入 csv 文件 {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(escape_csv("高等数学"), "高等数学");
+        assert_eq!(escape_csv(""), "");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(escape_csv("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn sanitize_file_names() {
+        assert_eq!(sanitize("第1章: 导论/上"), "第1章_ 导论_上");
+        assert_eq!(sanitize("a<b>|c?*\"d\\e"), "a_b__c___d_e");
+        assert_eq!(sanitize("普通名字.pdf"), "普通名字.pdf");
+    }
+}
@@ -0,0 +1,186 @@
+use reqwest::header::{HeaderMap, HeaderValue, COOKIE};
+use reqwest::Url;
+use soft_aes::aes::aes_enc_block;
+
+use crate::login::profile;
+use crate::setting::config;
+
+/// 校外通过 WebVPN 访问校内服务，地址改写为
+/// `https://webvpn.xmu.edu.cn/<协议[-端口]>/<加密后的主机名><路径>`
+pub const HOST: &str = "webvpn.xmu.edu.cn";
+pub const LOGIN_URL: &str = "https://webvpn.xmu.edu.cn/login?cas_login=true";
+const CAMPUS_DOMAIN: &str = "xmu.edu.cn";
+/// 主机名用 AES-128-CFB 加密，密钥和 IV 是门户页面中写死的
+const KEY: &[u8; 16] = b"wrdvpnisthebest!";
+const IV: &[u8; 16] = b"wrdvpnisthebest!";
+
+/// 已开启 WebVPN 且当前账号登录过门户
+pub fn active() -> bool {
+    config::get().webvpn && cookie().is_some()
+}
+
+pub fn cookie() -> Option<String> {
+    profile::current().webvpn_cookie
+}
+
+/// CFB 模式加解密相同，只是反馈的分组不同；最后一个分组可以不完整
+fn cfb(data: &[u8], decrypt: bool) -> Option<Vec<u8>> {
+    let mut feedback = *IV;
+    let mut ret = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let stream = aes_enc_block(&feedback, KEY).ok()?;
+        for (i, byte) in chunk.iter().enumerate() {
+            let out = byte ^ stream[i];
+            ret.push(out);
+            feedback[i] = if decrypt { *byte } else { out };
+        }
+    }
+    Some(ret)
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_host(host: &str) -> Option<String> {
+    Some(to_hex(IV) + &to_hex(&cfb(host.as_bytes(), false)?))
+}
+
+fn decode_host(text: &str) -> Option<String> {
+    let data = from_hex(text.strip_prefix(&to_hex(IV))?)?;
+    String::from_utf8(cfb(&data, true)?).ok()
+}
+
+fn is_campus(host: &str) -> bool {
+    host != HOST && (host == CAMPUS_DOMAIN || host.ends_with(&format!(".{}", CAMPUS_DOMAIN)))
+}
+
+/// 把校内地址改写为经过 WebVPN 的地址，返回是否改写
+pub fn rewrite(url: &mut Url) -> bool {
+    active() && rewrite_campus(url)
+}
+
+fn rewrite_campus(url: &mut Url) -> bool {
+    let host = match url.host_str() {
+        Some(v) if is_campus(v) => v.to_string(),
+        _ => return false,
+    };
+    let encoded = match encode_host(&host) {
+        Some(v) => v,
+        None => return false,
+    };
+    let protocol = match url.port() {
+        Some(port) => format!("{}-{}", url.scheme(), port),
+        None => url.scheme().to_string(),
+    };
+    let rewritten = format!("https://{}/{}/{}{}", HOST, protocol, encoded, url.path());
+    match Url::parse(&rewritten) {
+        Ok(mut v) => {
+            v.set_query(url.query());
+            v.set_fragment(url.fragment());
+            *url = v;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// `rewrite` 的逆过程，让调用者看到的仍是原来的地址
+pub fn restore(url: &Url) -> Url {
+    if url.host_str() != Some(HOST) {
+        return url.clone();
+    }
+    let mut segments = url.path().splitn(4, '/').skip(1);
+    let (protocol, host) = match (segments.next(), segments.next().and_then(decode_host)) {
+        (Some(p), Some(h)) => (p, h),
+        _ => return url.clone(),
+    };
+    let origin = match protocol.split_once('-') {
+        Some((scheme, port)) => format!("{}://{}:{}", scheme, host, port),
+        None => format!("{}://{}", protocol, host),
+    };
+    let path = segments.next().unwrap_or("");
+    match Url::parse(&format!("{}/{}", origin, path)) {
+        Ok(mut v) => {
+            v.set_query(url.query());
+            v.set_fragment(url.fragment());
+            v
+        }
+        Err(_) => url.clone(),
+    }
+}
+
+/// 在请求已有的 cookie 后面带上门户的登录凭据
+pub fn add_cookie(headers: &mut HeaderMap) {
+    let cookie = match cookie() {
+        Some(v) => v,
+        None => return,
+    };
+    let value = match headers.get(COOKIE).and_then(|x| x.to_str().ok()) {
+        Some(v) => format!("{}; {}", v, cookie),
+        None => cookie,
+    };
+    if let Ok(v) = HeaderValue::from_str(&value) {
+        headers.insert(COOKIE, v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> Url {
+        let mut url = Url::parse(text).unwrap();
+        assert!(rewrite_campus(&mut url));
+        assert_eq!(url.host_str(), Some(HOST));
+        let restored = restore(&url);
+        assert_eq!(restored.as_str(), Url::parse(text).unwrap().as_str());
+        url
+    }
+
+    #[test]
+    fn rewrites_campus_hosts() {
+        let url = round_trip("https://lnt.xmu.edu.cn/api/courses/1/activities?page=2#top");
+        assert!(url
+            .path()
+            .starts_with("/https/77726476706e69737468656265737421"));
+        assert_eq!(url.query(), Some("page=2"));
+        round_trip("http://jw.xmu.edu.cn:8080/jwapp/sys/a.do");
+        round_trip("https://xmu.edu.cn/");
+    }
+
+    #[test]
+    fn port_in_protocol() {
+        let mut url = Url::parse("http://jw.xmu.edu.cn:8080/a").unwrap();
+        rewrite_campus(&mut url);
+        assert!(url.path().starts_with("/http-8080/"));
+    }
+
+    #[test]
+    fn keeps_other_hosts() {
+        for text in [
+            "https://example.com/a.pdf",
+            "https://notxmu.edu.cn/",
+            "https://webvpn.xmu.edu.cn/login",
+        ] {
+            let mut url = Url::parse(text).unwrap();
+            assert!(!rewrite_campus(&mut url));
+            assert_eq!(url.as_str(), text);
+            assert_eq!(restore(&url).as_str(), text);
+        }
+    }
+
+    #[test]
+    fn host_cipher_round_trip() {
+        let encoded = encode_host("ids.xmu.edu.cn").unwrap();
+        assert_eq!(decode_host(&encoded).as_deref(), Some("ids.xmu.edu.cn"));
+        assert_eq!(decode_host("00"), None);
+    }
+}
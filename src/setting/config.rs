@@ -0,0 +1,242 @@
+use chrono::NaiveTime;
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::metadata;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::public::storage::{read_json, write_json, DATA_PATH};
+
+lazy_static! {
+    static ref CONFIG: Mutex<Config> = Mutex::new(load());
+    /// 上次读取令牌时设置文件的修改时间
+    static ref TOKEN_MODIFIED: Mutex<Option<SystemTime>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum QrMode {
+    /// 支持终端图片协议时直接显示图片，否则使用字符
+    #[default]
+    Auto,
+    Full,
+    Compact,
+    Inline,
+    Viewer,
+}
+
+impl QrMode {
+    pub const ALL: [QrMode; 5] = [
+        QrMode::Auto,
+        QrMode::Full,
+        QrMode::Compact,
+        QrMode::Inline,
+        QrMode::Viewer,
+    ];
+    pub fn name(&self) -> &'static str {
+        match *self {
+            QrMode::Auto => "自动",
+            QrMode::Full => "字符",
+            QrMode::Compact => "紧凑半块字符",
+            QrMode::Inline => "终端图片（sixel/kitty/iTerm）",
+            QrMode::Viewer => "系统图片查看器",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum DedupMode {
+    Off,
+    /// 只在日志和报告中列出重复的文件
+    #[default]
+    Report,
+    /// 重复的文件只保留一份，其余位置用硬链接代替
+    HardLink,
+}
+
+impl DedupMode {
+    pub const ALL: [DedupMode; 3] = [DedupMode::Off, DedupMode::Report, DedupMode::HardLink];
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DedupMode::Off => "关闭",
+            DedupMode::Report => "只报告",
+            DedupMode::HardLink => "硬链接合并",
+        }
+    }
+}
+
+/// 要保存的文件名已经存在或正在下载时的处理，内容相同的文件除覆盖外都直接跳过，
+/// 同步时总是跳过
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// 内容不同时下载新的版本覆盖旧文件
+    #[default]
+    SkipIdentical,
+    Overwrite,
+    /// 在文件名后加 ` (1)` 这样的序号
+    Rename,
+    /// 在文件名后加上课程中心的 reference_id
+    KeepBoth,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 4] = [
+        ConflictPolicy::SkipIdentical,
+        ConflictPolicy::Overwrite,
+        ConflictPolicy::Rename,
+        ConflictPolicy::KeepBoth,
+    ];
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ConflictPolicy::SkipIdentical => "相同则跳过，不同则更新",
+            ConflictPolicy::Overwrite => "总是覆盖",
+            ConflictPolicy::Rename => "相同则跳过，不同则加序号",
+            ConflictPolicy::KeepBoth => "相同则跳过，不同则加 reference_id",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub qrcode_mode: QrMode,
+    /// 字符显示时按浅色背景的终端绘制，图片显示不受影响
+    pub qrcode_invert: bool,
+    /// 查询扫码状态的间隔，请求过于频繁时会自动加倍
+    pub qrcode_poll_interval_ms: u64,
+    pub qrcode_timeout_secs: u64,
+    /// 二维码过期后自动刷新的次数上限
+    pub qrcode_max_renew: u32,
+    /// 本地接口只监听 127.0.0.1
+    pub api_port: u16,
+    /// 为空时第一次启动本地接口会自动生成
+    pub api_token: String,
+    pub notify: NotifyConfig,
+    /// 全局下载限速（KB/s），0 为不限
+    pub speed_limit_kb: u64,
+    /// 同一主机同时下载的数量，0 为不限
+    pub per_host_limit: usize,
+    /// 按时段覆盖全局限速，先匹配到的生效
+    pub speed_schedules: Vec<SpeedSchedule>,
+    /// 如 `http://127.0.0.1:7890` 或 `socks5h://127.0.0.1:1080`，为空时使用系统代理
+    pub proxy: String,
+    /// 通过 WebVPN 访问校内服务，需要先登录 WebVPN
+    pub webvpn: bool,
+    pub dedup: DedupMode,
+    pub conflict: ConflictPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedSchedule {
+    pub start: NaiveTime,
+    /// 早于 start 时表示跨过午夜
+    pub end: NaiveTime,
+    pub limit_kb: u64,
+}
+
+impl SpeedSchedule {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match self.start <= self.end {
+            true => self.start <= time && time < self.end,
+            false => time >= self.start || time < self.end,
+        }
+    }
+    pub fn describe(&self) -> String {
+        let limit = match self.limit_kb {
+            0 => "不限速".to_string(),
+            kb => format!("{} KB/s", kb),
+        };
+        format!(
+            "{}-{} {}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M"),
+            limit
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub desktop: bool,
+    /// 为空时不发送
+    pub webhook_url: String,
+    pub smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            qrcode_mode: QrMode::default(),
+            qrcode_invert: false,
+            qrcode_poll_interval_ms: 1000,
+            qrcode_timeout_secs: 300,
+            qrcode_max_renew: 3,
+            api_port: 8765,
+            api_token: String::new(),
+            notify: NotifyConfig::default(),
+            speed_limit_kb: 0,
+            per_host_limit: 0,
+            speed_schedules: Vec::new(),
+            proxy: String::new(),
+            webvpn: false,
+            dedup: DedupMode::default(),
+            conflict: ConflictPolicy::default(),
+        }
+    }
+}
+
+fn config_path() -> String {
+    format!("{}settings.json", DATA_PATH)
+}
+
+fn load() -> Config {
+    read_json(&config_path())
+        .and_then(|x| serde_json::from_value(x).ok())
+        .unwrap_or_default()
+}
+
+/// 本地接口的令牌，设置文件被修改过时只重新读取令牌，
+/// 在其他进程中重新生成令牌后旧的也立即失效
+pub fn api_token() -> String {
+    let path = config_path();
+    let modified = metadata(&path).and_then(|x| x.modified()).ok();
+    let mut seen = TOKEN_MODIFIED.lock().unwrap();
+    if modified.is_some() && modified != *seen {
+        // 正在被写入读不出来时保留原来的，下次再读
+        if let Some(json) = read_json(&path) {
+            *seen = modified;
+            let token = json.get("api_token").and_then(|x| x.as_str()).unwrap_or("");
+            CONFIG.lock().unwrap().api_token = token.to_string();
+        }
+    }
+    drop(seen);
+    CONFIG.lock().unwrap().api_token.clone()
+}
+
+pub fn get() -> Config {
+    CONFIG.lock().unwrap().clone()
+}
+
+/// 修改设置并写回文件
+pub fn update<F: FnOnce(&mut Config)>(f: F) {
+    let mut lock = CONFIG.lock().unwrap();
+    f(&mut lock);
+    let ret = serde_json::to_value(&*lock)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| write_json(&config_path(), &x));
+    if let Err(e) = ret {
+        warn!("保存设置失败 {}", e);
+    }
+}
@@ -1,23 +1,299 @@
+use chrono::NaiveTime;
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::Select;
+use dialoguer::{Input, Select};
+use log::warn;
 
+use super::config::{self, ConflictPolicy, DedupMode, QrMode, SmtpConfig, SpeedSchedule};
+use crate::course_downloader::main::download_path;
+use crate::public::dedup;
 use crate::public::download_file;
+use crate::public::http;
+use crate::public::notify;
+use crate::public::webvpn;
 
 pub fn main() {
     let selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("选择设置")
         .default(0)
         .item("设置下载线程数量")
+        .item("二维码显示方式")
+        .item("二维码反色")
+        .item("扫码登录轮询设置")
+        .item("通知设置")
+        .item("限速设置")
+        .item("代理和 WebVPN")
+        .item("重复文件")
+        .item("文件名冲突")
         .item("返回")
         .interact()
         .unwrap_or(1000);
     match selection {
         0 => set_num_threads(),
-        1 => {}
+        1 => set_qrcode_mode(),
+        2 => set_qrcode_invert(),
+        3 => set_qrcode_poll(),
+        4 => set_notify(),
+        5 => set_speed_limit(),
+        6 => set_network(),
+        7 => set_dedup(),
+        8 => set_conflict(),
+        9 => {}
         _ => {}
     }
 }
 
+fn set_qrcode_mode() {
+    let current = config::get().qrcode_mode;
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("选择二维码显示方式")
+        .items(&QrMode::ALL.iter().map(|x| x.name()).collect::<Vec<_>>())
+        .default(QrMode::ALL.iter().position(|x| *x == current).unwrap_or(0))
+        .interact()
+        .unwrap_or(0);
+    config::update(|x| x.qrcode_mode = QrMode::ALL[selection]);
+}
+
+fn set_qrcode_invert() {
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("二维码颜色")
+        .item("正常（深色背景终端）")
+        .item("反色（浅色背景终端）")
+        .default(config::get().qrcode_invert as usize)
+        .interact()
+        .unwrap_or(0);
+    config::update(|x| x.qrcode_invert = selection == 1);
+}
+
+fn input_number(prompt: &str, default: u64) -> u64 {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact_text()
+        .unwrap_or(default)
+}
+
+fn set_qrcode_poll() {
+    let current = config::get();
+    let interval = input_number("查询间隔（毫秒）", current.qrcode_poll_interval_ms);
+    let timeout = input_number("超时时间（秒）", current.qrcode_timeout_secs);
+    let renew = input_number("二维码过期后自动刷新次数", current.qrcode_max_renew as u64);
+    config::update(|x| {
+        x.qrcode_poll_interval_ms = interval;
+        x.qrcode_timeout_secs = timeout;
+        x.qrcode_max_renew = renew as u32;
+    });
+}
+
+fn input_text(prompt: &str, default: String) -> String {
+    Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn set_smtp() {
+    let current = config::get().notify.smtp.unwrap_or_default();
+    let server = input_text("SMTP 服务器（留空关闭邮件通知）", current.server);
+    if server.is_empty() {
+        config::update(|x| x.notify.smtp = None);
+        return;
+    }
+    let smtp = SmtpConfig {
+        server,
+        port: input_number("端口（0 为默认 465）", current.port as u64) as u16,
+        username: input_text("用户名", current.username),
+        password: input_text("密码或授权码", current.password),
+        from: input_text("发件人", current.from),
+        to: input_text("收件人", current.to),
+    };
+    config::update(|x| x.notify.smtp = Some(smtp));
+}
+
+fn set_notify() {
+    let current = config::get().notify;
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("通知设置")
+        .default(0)
+        .item(match current.desktop {
+            true => "桌面通知：开",
+            false => "桌面通知：关",
+        })
+        .item(match current.webhook_url.is_empty() {
+            true => "Webhook：未设置".to_string(),
+            false => format!("Webhook：{}", current.webhook_url),
+        })
+        .item(match &current.smtp {
+            Some(smtp) => format!("邮件：{}", smtp.to),
+            None => "邮件：未设置".to_string(),
+        })
+        .item("发送测试通知")
+        .item("返回")
+        .interact()
+        .unwrap_or(4);
+    match selection {
+        0 => config::update(|x| x.notify.desktop = !x.notify.desktop),
+        1 => {
+            let url = input_text("Webhook 地址（留空关闭）", current.webhook_url);
+            config::update(|x| x.notify.webhook_url = url);
+        }
+        2 => set_smtp(),
+        3 => notify::send_test(),
+        _ => {}
+    }
+}
+
+/// 解析 `22:00-07:00 0` 形式的时段，最后的数字为限速（KB/s）
+fn parse_schedule(text: &str) -> Option<SpeedSchedule> {
+    let (range, limit) = text.split_once(' ')?;
+    let (start, end) = range.split_once('-')?;
+    Some(SpeedSchedule {
+        start: NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+        end: NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+        limit_kb: limit.trim().parse().ok()?,
+    })
+}
+
+fn set_speed_limit() {
+    let current = config::get();
+    let mut items = vec![
+        match current.speed_limit_kb {
+            0 => "全局限速：不限".to_string(),
+            kb => format!("全局限速：{} KB/s", kb),
+        },
+        match current.per_host_limit {
+            0 => "每个主机连接数：不限".to_string(),
+            n => format!("每个主机连接数：{}", n),
+        },
+    ];
+    items.extend(
+        current
+            .speed_schedules
+            .iter()
+            .map(|x| format!("删除时段 {}", x.describe())),
+    );
+    items.push("添加时段".to_string());
+    items.push("返回".to_string());
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("限速设置（时段内的限速优先）")
+        .default(0)
+        .items(&items)
+        .interact()
+        .unwrap_or(items.len() - 1);
+    let schedules = current.speed_schedules.len();
+    match selection {
+        0 => {
+            let kb = input_number("全局限速（KB/s，0 为不限）", current.speed_limit_kb);
+            config::update(|x| x.speed_limit_kb = kb);
+        }
+        1 => {
+            let n = input_number("每个主机连接数（0 为不限）", current.per_host_limit as u64);
+            config::update(|x| x.per_host_limit = n as usize);
+        }
+        i if i < schedules + 2 => config::update(|x| {
+            x.speed_schedules.remove(i - 2);
+        }),
+        i if i == schedules + 2 => {
+            let text = input_text("时段和限速，如 23:00-07:00 0", String::new());
+            match parse_schedule(&text) {
+                Some(v) => config::update(|x| x.speed_schedules.push(v)),
+                None => warn!("格式错误：{}", text),
+            }
+        }
+        _ => {}
+    }
+}
+
+fn set_network() {
+    let current = config::get();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("代理和 WebVPN")
+        .default(0)
+        .item(match current.proxy.is_empty() {
+            true => "代理：系统代理".to_string(),
+            false => format!("代理：{}", current.proxy),
+        })
+        .item(match (current.webvpn, webvpn::cookie().is_some()) {
+            (true, true) => "WebVPN：开",
+            (true, false) => "WebVPN：开（未登录，请在登录账号中登录 WebVPN）",
+            (false, _) => "WebVPN：关",
+        })
+        .item("返回")
+        .interact()
+        .unwrap_or(2);
+    match selection {
+        0 => {
+            let proxy = input_text(
+                "代理地址，如 http://127.0.0.1:7890 或 socks5h://127.0.0.1:1080（留空使用系统代理）",
+                current.proxy,
+            );
+            config::update(|x| x.proxy = proxy);
+            http::reload();
+        }
+        1 => config::update(|x| x.webvpn = !x.webvpn),
+        _ => {}
+    }
+}
+
+fn set_dedup() {
+    let current = config::get().dedup;
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("重复文件")
+        .default(0)
+        .item(format!("处理方式：{}", current.name()))
+        .item("扫描下载目录")
+        .item("查看重复文件和节省的空间")
+        .item("返回")
+        .interact()
+        .unwrap_or(3);
+    match selection {
+        0 => {
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("发现内容相同的文件时")
+                .items(&DedupMode::ALL.iter().map(|x| x.name()).collect::<Vec<_>>())
+                .default(
+                    DedupMode::ALL
+                        .iter()
+                        .position(|x| *x == current)
+                        .unwrap_or(0),
+                )
+                .interact()
+                .unwrap_or(0);
+            config::update(|x| x.dedup = DedupMode::ALL[selection]);
+        }
+        1 => {
+            dedup::scan(&download_path());
+            dedup::print_report();
+        }
+        2 => dedup::print_report(),
+        _ => {}
+    }
+}
+
+fn set_conflict() {
+    let current = config::get().conflict;
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("要保存的文件已经存在时")
+        .items(
+            &ConflictPolicy::ALL
+                .iter()
+                .map(|x| x.name())
+                .collect::<Vec<_>>(),
+        )
+        .default(
+            ConflictPolicy::ALL
+                .iter()
+                .position(|x| *x == current)
+                .unwrap_or(0),
+        )
+        .interact()
+        .unwrap_or(0);
+    config::update(|x| x.conflict = ConflictPolicy::ALL[selection]);
+}
+
 fn set_num_threads() {
     let selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("选择下载线程数量")
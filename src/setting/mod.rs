@@ -1,2 +1,3 @@
+pub mod config;
 mod main;
 pub use main::main;
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Input, Select};
+use log::{debug, info, trace, warn, LevelFilter};
+use reqwest::multipart::Part;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::path::Path;
+
+use crate::course_downloader::course::{get_cookie, load_courses};
+use crate::course_downloader::download::{post_json_with_cookie, upload_with_cookie};
+use crate::course_downloader::main::Error as CourseError;
+use crate::deadlines::main::{
+    get_course_deadlines, get_submissions, submissions_url, Deadline, Kind,
+};
+use crate::public::http::reader_body;
+use crate::public::logger::{Logger, LoggerData};
+use crate::public::progress::{format_size, ProgressReader};
+use crate::public::VOID_VEC;
+
+pub enum Error {
+    Course(CourseError),
+    FileOpen,
+    NoHomework,
+    Upload,
+    Submit,
+    Cancel,
+}
+
+impl Logger for Error {
+    fn get_logger(&self) -> LoggerData {
+        match self {
+            Error::Course(e) => e.get_logger(),
+            Error::FileOpen => LoggerData::new(LevelFilter::Error, "无法打开要提交的文件"),
+            Error::NoHomework => LoggerData::new(LevelFilter::Warn, "该课程没有可以提交的作业"),
+            Error::Upload => LoggerData::new(LevelFilter::Error, "文件上传失败，请重试"),
+            Error::Submit => LoggerData::new(LevelFilter::Error, "作业提交失败，请重试"),
+            Error::Cancel => LoggerData::new(LevelFilter::Info, "已取消提交"),
+        }
+    }
+}
+
+impl From<CourseError> for Error {
+    fn from(e: CourseError) -> Self {
+        Error::Course(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(_: std::io::Error) -> Self {
+        Error::FileOpen
+    }
+}
+
+pub fn main() {
+    match submit() {
+        Ok(_) => {}
+        Err(e) => e.logger(),
+    }
+}
+
+fn select_homework(cookie: &str) -> Result<Deadline, Error> {
+    let courses = load_courses(cookie, false)?;
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("请选择课程")
+        .items(
+            &courses
+                .iter()
+                .map(|x| format!("{} {} {}", x.name, x.instructors, x.semester))
+                .collect::<Vec<_>>(),
+        )
+        .interact_opt()
+        .unwrap_or(None)
+        .ok_or(Error::Cancel)?;
+    let mut homeworks = get_course_deadlines(&courses[selection], cookie)?
+        .into_iter()
+        .filter(|x| x.kind == Kind::Homework)
+        .collect::<Vec<_>>();
+    if homeworks.is_empty() {
+        return Err(Error::NoHomework);
+    }
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("请选择作业")
+        .items(
+            &homeworks
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{} 截止 {} {}",
+                        x.title,
+                        x.end
+                            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                            .unwrap_or("无".to_string()),
+                        x.status()
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+        .interact_opt()
+        .unwrap_or(None)
+        .ok_or(Error::Cancel)?;
+    Ok(homeworks.swap_remove(selection))
+}
+
+/// 上传文件到 lnt，返回 upload id
+fn upload(path: &Path, cookie: &str) -> Result<u64, Error> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let resp = post_json_with_cookie(
+        "https://lnt.xmu.edu.cn/api/uploads",
+        &json!({
+            "name": name,
+            "size": size,
+            "parent_id": 0,
+            "is_scorm": false,
+            "is_wmpkg": false,
+            "source": "",
+        }),
+        cookie,
+    )?;
+    let json: Value = resp.json().unwrap_or(Value::Null);
+    trace!("创建上传 json = {}", json);
+    let id = json
+        .get("id")
+        .and_then(Value::as_u64)
+        .ok_or(Error::Upload)?;
+    let upload_url = json
+        .get("upload_url")
+        .unwrap_or(&Value::Null)
+        .as_str()
+        .ok_or(Error::Upload)?;
+    debug!("获取到 upload id = {} upload_url = {}", id, upload_url);
+
+    let body = reader_body(ProgressReader::new(file, size));
+    let part = Part::stream_with_length(body, size).file_name(name);
+    let resp = upload_with_cookie(upload_url, part, cookie)?;
+    if !resp.status().is_success() {
+        warn!("上传返回状态码 {}", resp.status());
+        return Err(Error::Upload);
+    }
+    Ok(id)
+}
+
+/// 提交记录的附件中有刚上传的文件，附件可能是 id 或带 id 的对象
+fn has_upload(submissions: &[Value], upload_id: u64) -> bool {
+    submissions.iter().any(|submission| {
+        submission
+            .get("uploads")
+            .and_then(|x| x.as_array())
+            .unwrap_or(&*VOID_VEC)
+            .iter()
+            .any(|x| x.as_u64().or(x.get("id").and_then(Value::as_u64)) == Some(upload_id))
+    })
+}
+
+fn submit() -> Result<(), Error> {
+    let cookie = get_cookie()?;
+    let homework = select_homework(&cookie)?;
+
+    let path: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("请输入要提交的文件路径")
+        .interact_text()
+        .map_err(|_| Error::Cancel)?;
+    let path = Path::new(path.trim());
+    let size = File::open(path)?.metadata()?.len();
+
+    println!("课程：{}", homework.course.name);
+    println!("作业：{}", homework.title);
+    println!(
+        "截止：{}",
+        homework
+            .end
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or("无".to_string())
+    );
+    println!("当前状态：{}", homework.status());
+    println!("文件：{} ({})", path.display(), format_size(size));
+    let confirm = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("确认提交？")
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+    if !confirm {
+        return Err(Error::Cancel);
+    }
+
+    let upload_id = upload(path, &cookie)?;
+    // 提交记录由课程活动的接口创建，查询时和作业截止状态用同一个接口
+    let resp = post_json_with_cookie(
+        format!(
+            "https://lnt.xmu.edu.cn/api/course/activities/{}/submissions",
+            homework.id
+        ),
+        &json!({
+            "comment": "",
+            "uploads": [upload_id],
+            "slides": [],
+            "is_draft": false,
+            "mode": "normal",
+            "other_resources": [],
+            "uploads_in_rich_text": [],
+        }),
+        &cookie,
+    )?;
+    if !resp.status().is_success() {
+        warn!("提交返回状态码 {}", resp.status());
+        return Err(Error::Submit);
+    }
+    trace!("提交 json = {}", resp.text());
+    let url = submissions_url(Kind::Homework, &homework.id);
+    let submissions = get_submissions(&url, &cookie)?.unwrap_or_default();
+    if !has_upload(&submissions, upload_id) {
+        warn!("提交记录中没有刚上传的文件，请到网页端确认");
+        return Err(Error::Submit);
+    }
+    info!("提交成功：{} {}", homework.course.name, homework.title);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submission_must_contain_upload() {
+        let submissions = vec![
+            json!({"id": 1, "uploads": [{"id": 7}]}),
+            json!({"id": 2, "uploads": [{"id": 42, "name": "a.pdf"}]}),
+        ];
+        assert!(has_upload(&submissions, 42));
+        assert!(has_upload(&[json!({"id": 1, "uploads": [42]})], 42));
+        assert!(!has_upload(&submissions, 8));
+        assert!(!has_upload(&[json!({"id": 1})], 42));
+        assert!(!has_upload(&[], 42));
+    }
+}
@@ -0,0 +1,236 @@
+use super::ui;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use lazy_static::lazy_static;
+use log::info;
+use ratatui::widgets::ListState;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::course_downloader::course::{get_cookie, load_courses, Course};
+use crate::course_downloader::main::queue_course;
+use crate::login::main::{qr_login, Target};
+use crate::public::download_file::{
+    active_tasks, cancel_all, cancel_task, failed_tasks, retry_error_task, retry_error_tasks,
+};
+use crate::public::logger::{start_capture, stop_capture, Logger};
+use crate::public::thread_manage;
+
+lazy_static! {
+    /// 扫码登录过程中需要展示的二维码
+    pub static ref QRCODE: Mutex<Option<String>> = Mutex::new(None);
+}
+static CANCEL_LOGIN: AtomicBool = AtomicBool::new(false);
+
+/// 登录线程在两次查询之间等待，界面上按 q 或 Esc 时取消
+fn wait_cancel(duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if CANCEL_LOGIN.swap(false, Ordering::SeqCst) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pane {
+    Courses,
+    Queue,
+    Failed,
+    Logs,
+}
+
+impl Pane {
+    fn next(&self) -> Self {
+        match *self {
+            Pane::Courses => Pane::Queue,
+            Pane::Queue => Pane::Failed,
+            Pane::Failed => Pane::Logs,
+            Pane::Logs => Pane::Courses,
+        }
+    }
+}
+
+pub struct App {
+    pub pane: Pane,
+    pub courses: Arc<Mutex<Vec<Course>>>,
+    pub course_state: ListState,
+    pub queue_state: ListState,
+    pub failed_state: ListState,
+    /// 日志从底部往上滚动的行数
+    pub log_scroll: usize,
+}
+
+impl App {
+    fn new() -> Self {
+        Self {
+            pane: Pane::Courses,
+            courses: Arc::new(Mutex::new(Vec::new())),
+            course_state: ListState::default(),
+            queue_state: ListState::default(),
+            failed_state: ListState::default(),
+            log_scroll: 0,
+        }
+    }
+
+    fn refresh_courses(&self, refresh: bool) {
+        let cookie = match get_cookie() {
+            Ok(v) => v,
+            Err(e) => return e.logger(),
+        };
+        let courses = Arc::clone(&self.courses);
+        thread_manage::execute("课程列表线程", move || {
+            match load_courses(&cookie, refresh) {
+                Ok(v) => {
+                    info!("获取到 {} 门课程", v.len());
+                    *courses.lock().unwrap() = v;
+                }
+                Err(e) => e.logger(),
+            }
+        });
+    }
+
+    fn login(&self) {
+        if QRCODE.lock().unwrap().is_some() {
+            return;
+        }
+        let courses = Arc::clone(&self.courses);
+        thread_manage::execute("登录线程", move || {
+            CANCEL_LOGIN.store(false, Ordering::SeqCst);
+            let ret = qr_login(
+                Target::Lnt,
+                &|x| {
+                    *QRCODE.lock().unwrap() = Some(x.render()?);
+                    Ok(())
+                },
+                &wait_cancel,
+            );
+            *QRCODE.lock().unwrap() = None;
+            match ret {
+                Ok(_) => {
+                    info!("登录成功");
+                    let loaded = get_cookie().and_then(|cookie| load_courses(&cookie, false));
+                    match loaded {
+                        Ok(v) => *courses.lock().unwrap() = v,
+                        Err(e) => e.logger(),
+                    }
+                }
+                Err(e) => e.logger(),
+            }
+        });
+    }
+
+    fn queue_selected_course(&self) {
+        let course = match self.course_state.selected() {
+            Some(i) => self.courses.lock().unwrap().get(i).cloned(),
+            None => None,
+        };
+        let (course, cookie) = match (course, get_cookie()) {
+            (Some(c), Ok(cookie)) => (c, cookie),
+            (_, Err(e)) => return e.logger(),
+            _ => return,
+        };
+        thread_manage::execute("课程同步线程", move || {
+            match queue_course(&course.id, &cookie) {
+                Ok(n) => info!("{} 已放入 {} 个下载任务", course.name, n),
+                Err(e) => e.logger(),
+            }
+        });
+    }
+
+    fn cancel_selected(&self) {
+        if self.pane != Pane::Queue {
+            return;
+        }
+        let selected = self.queue_state.selected();
+        if let Some(state) = selected.and_then(|i| active_tasks().into_iter().nth(i)) {
+            cancel_task(state.task.id);
+        }
+    }
+
+    fn move_selection(&mut self, up: bool) {
+        let (state, len) = match self.pane {
+            Pane::Courses => (&mut self.course_state, self.courses.lock().unwrap().len()),
+            Pane::Queue => (&mut self.queue_state, active_tasks().len()),
+            Pane::Failed => (&mut self.failed_state, failed_tasks().len()),
+            Pane::Logs => {
+                self.log_scroll = match up {
+                    true => self.log_scroll + 1,
+                    false => self.log_scroll.saturating_sub(1),
+                };
+                return;
+            }
+        };
+        if len == 0 {
+            return;
+        }
+        let selected = state.selected().unwrap_or(0);
+        state.select(Some(match up {
+            true => selected.saturating_sub(1),
+            false => (selected + 1).min(len - 1),
+        }));
+    }
+
+    fn enter(&mut self) {
+        match self.pane {
+            Pane::Courses => self.queue_selected_course(),
+            Pane::Failed => {
+                if let Some(i) = self.failed_state.selected() {
+                    retry_error_task(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 处理一个按键，返回 false 表示退出
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let logging_in = QRCODE.lock().unwrap().is_some();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc if logging_in => {
+                CANCEL_LOGIN.store(true, Ordering::SeqCst)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab => self.pane = self.pane.next(),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(true),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(false),
+            KeyCode::Enter => self.enter(),
+            KeyCode::Char('l') => self.login(),
+            KeyCode::Char('r') => retry_error_tasks(),
+            KeyCode::Char('c') => self.cancel_selected(),
+            KeyCode::Char('C') => cancel_all(),
+            KeyCode::Char('R') => self.refresh_courses(true),
+            _ => {}
+        }
+        true
+    }
+}
+
+pub fn main() {
+    start_capture();
+    let mut terminal = ratatui::init();
+    let mut app = App::new();
+    if get_cookie().is_ok() {
+        app.refresh_courses(false);
+    }
+    loop {
+        if terminal.draw(|frame| ui::draw(frame, &mut app)).is_err() {
+            break;
+        }
+        match event::poll(Duration::from_millis(200)) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => break,
+        }
+        if let Ok(Event::Key(key)) = event::read() {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                break;
+            }
+        }
+    }
+    ratatui::restore();
+    stop_capture();
+}
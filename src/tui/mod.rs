@@ -0,0 +1,3 @@
+pub mod main;
+pub mod ui;
+pub use main::main;
@@ -0,0 +1,144 @@
+use super::main::{App, Pane, QRCODE};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, Paragraph};
+use ratatui::Frame;
+
+use crate::login::main::get_session;
+use crate::public::download_file::{active_tasks, failed_tasks};
+use crate::public::logger::captured;
+use crate::public::progress::render_bar;
+
+const HELP: &str =
+    "Tab 切换面板  ↑↓/jk 移动  Enter 下载课程/重试任务  c/C 取消任务/全部  l 扫码登录  r 重试全部  R 刷新课程  q 退出";
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = match focused {
+        true => Style::default().fg(Color::Yellow),
+        false => Style::default(),
+    };
+    Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(style)
+}
+
+fn highlight() -> Style {
+    Style::default()
+        .bg(Color::DarkGray)
+        .add_modifier(Modifier::BOLD)
+}
+
+fn file_name(file: &str) -> &str {
+    file.rsplit('/').next().unwrap_or(file)
+}
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, logs, help] = Layout::vertical([
+        Constraint::Min(10),
+        Constraint::Length(10),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [courses, right] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+    let [queue, failed] =
+        Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(right);
+
+    draw_courses(frame, app, courses);
+    draw_queue(frame, app, queue);
+    draw_failed(frame, app, failed);
+    draw_logs(frame, app, logs);
+    frame.render_widget(Paragraph::new(HELP), help);
+    draw_qrcode(frame);
+}
+
+fn draw_courses(frame: &mut Frame, app: &mut App, area: Rect) {
+    let courses = app.courses.lock().unwrap().clone();
+    let title = match get_session() {
+        Some(_) => format!("课程（{}）", courses.len()),
+        None => "课程（未登录，按 l 扫码登录）".to_string(),
+    };
+    let items = courses
+        .iter()
+        .map(|x| ListItem::new(format!("{} {} {}", x.name, x.instructors, x.semester)))
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(block(title, app.pane == Pane::Courses))
+        .highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.course_state);
+}
+
+fn draw_queue(frame: &mut Frame, app: &mut App, area: Rect) {
+    let tasks = active_tasks();
+    let items = tasks
+        .iter()
+        .map(|x| {
+            let state = match x.started {
+                true => render_bar(x.current, x.total),
+                false => "等待中".to_string(),
+            };
+            ListItem::new(format!("{} {}", file_name(&x.task.file), state))
+        })
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(block(
+            format!("下载队列（{}）", tasks.len()),
+            app.pane == Pane::Queue,
+        ))
+        .highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.queue_state);
+}
+
+fn draw_failed(frame: &mut Frame, app: &mut App, area: Rect) {
+    let tasks = failed_tasks();
+    let items = tasks
+        .iter()
+        .map(|x| ListItem::new(file_name(&x.file).to_string()))
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(block(
+            format!("失败任务（{}）", tasks.len()),
+            app.pane == Pane::Failed,
+        ))
+        .highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.failed_state);
+}
+
+fn draw_logs(frame: &mut Frame, app: &mut App, area: Rect) {
+    let lines = captured();
+    let height = area.height.saturating_sub(2) as usize;
+    app.log_scroll = app.log_scroll.min(lines.len().saturating_sub(height));
+    let end = lines.len() - app.log_scroll;
+    let start = end.saturating_sub(height);
+    let text = lines[start..end]
+        .iter()
+        .map(|x| Line::from(x.as_str()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(text).block(block("日志".to_string(), app.pane == Pane::Logs)),
+        area,
+    );
+}
+
+fn draw_qrcode(frame: &mut Frame) {
+    let qrcode = match QRCODE.lock().unwrap().clone() {
+        Some(v) => v,
+        None => return,
+    };
+    let width = qrcode.lines().map(|x| x.chars().count()).max().unwrap_or(0) as u16 + 2;
+    let height = qrcode.lines().count() as u16 + 2;
+    let area = frame.area();
+    let rect = Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width: width.min(area.width),
+        height: height.min(area.height),
+    };
+    frame.render_widget(Clear, rect);
+    frame.render_widget(
+        Paragraph::new(qrcode).block(Block::default().title("扫码登录").borders(Borders::ALL)),
+        rect,
+    );
+}